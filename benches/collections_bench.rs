@@ -0,0 +1,96 @@
+#![feature(test)]
+extern crate adapton;
+extern crate test;
+use self::test::Bencher;
+use adapton::catalog::collections::*;
+use adapton::engine::*;
+use adapton::engine::manage::*;
+
+// Each iteration edits one element of the input list, and then
+// rebuilds its tree and re-demands the whole output of a collection
+// program, as in the catalog's tests; under the DCG engine, this
+// dirties and cleans the DCG.
+mod collection_edits {
+    use super::*;
+
+    const LEN : usize = 500;
+
+    // The input list, with a name and a cell before each element; its
+    // middle element is `x`.
+    fn input(x:usize) -> List<usize> {
+        let mut l: List<usize> = List::nil();
+        for i in (1..LEN).into_iter() {
+            let l_ = List::art(cell(name_of_usize(i), l));
+            let l_ = List::name(name_of_usize(i), l_);
+            l = List::cons(if i == LEN / 2 { x } else { i * 7 % LEN }, l_)
+        }
+        l
+    }
+
+    fn tree(l:List<usize>) -> Tree<usize> {
+        ns(name_of_str("tree_of_list"),
+           ||tree_of_list::<_,_,Tree<_>,_>(Dir2::Left, l))
+    }
+
+    fn mergesort(x:usize) -> usize {
+        let t = tree(input(x));
+        let s = ns(name_of_str("mergesort"),
+                   ||mergesort_list_of_tree::<_,_,_,List<_>>(t));
+        vec_of_list(s, None).len()
+    }
+
+    fn filter(x:usize) -> usize {
+        let t = tree(input(x));
+        let s = ns(name_of_str("filter"),
+                   ||filter_list_of_tree::<_,_,List<_>,_>(t, Box::new(|x| x % 2 == 0)));
+        vec_of_list(s, None).len()
+    }
+
+    fn reverse(x:usize) -> usize {
+        let t = tree(input(x));
+        let s = ns(name_of_str("list_of_tree"),
+                   ||list_of_tree::<_,_,List<_>,_>(t, Dir2::Right));
+        vec_of_list(s, None).len()
+    }
+
+    fn run_bench(b: &mut Bencher, program:fn(usize) -> usize) {
+        let mut x = 0;
+        b.iter(|| { x += 1; program(x) })
+    }
+
+    #[bench]
+    fn benchmark_naive_mergesort_edit(b: &mut Bencher) {
+        init_naive();
+        run_bench(b, mergesort);
+    }
+
+    #[bench]
+    fn benchmark_dcg_mergesort_edit(b: &mut Bencher) {
+        init_dcg();
+        run_bench(b, mergesort);
+    }
+
+    #[bench]
+    fn benchmark_naive_filter_edit(b: &mut Bencher) {
+        init_naive();
+        run_bench(b, filter);
+    }
+
+    #[bench]
+    fn benchmark_dcg_filter_edit(b: &mut Bencher) {
+        init_dcg();
+        run_bench(b, filter);
+    }
+
+    #[bench]
+    fn benchmark_naive_reverse_edit(b: &mut Bencher) {
+        init_naive();
+        run_bench(b, reverse);
+    }
+
+    #[bench]
+    fn benchmark_dcg_reverse_edit(b: &mut Bencher) {
+        init_dcg();
+        run_bench(b, reverse);
+    }
+}
@@ -0,0 +1,48 @@
+#!/bin/sh
+# Runs the DCG benchmarks of this tree against the engine at each of
+# the given git revisions, and prints their times (in ns/iter) side by
+# side.  By default, the revisions are the DCG before its arena
+# (`baseline`), with its arena (`arena`), and with its arena and
+# location map in persistent tries (`persistent`).
+#
+# Usage: benches/compare.sh [rev ...]
+#
+# Each revision is checked out into a temporary directory, with this
+# tree's bench files, and benchmarked there on nightly (for
+# `#![feature(test)]`).  Recent nightlies no longer have the
+# `box_syntax` feature, which the crate enables but does not use; it
+# is dropped from each checkout.
+set -e
+cd "$(git rev-parse --show-toplevel)"
+names=""
+if [ $# -eq 0 ]; then
+  set -- c93377d~1 3fbc261~1 3fbc261
+  names="baseline arena persistent"
+fi
+work=$(mktemp -d)
+trap 'rm -rf "$work"' EXIT
+col=0
+for rev in "$@"; do
+  col=$((col + 1))
+  dir="$work/$col"
+  mkdir "$dir"
+  git archive "$rev" | tar -x -C "$dir"
+  rm -rf "$dir/benches" && cp -r benches "$dir/benches"
+  sed -i '/feature(box_syntax)/d' "$dir/src/lib.rs"
+  (cd "$dir" && cargo +nightly bench --bench tries_bench --bench collections_bench -- dcg) \
+    | awk -v col=$col '/bench:/ { gsub(",", "", $5); print $2, col, $5 }' >> "$work/results"
+done
+{
+  printf "%-50s" "benchmark"
+  col=0
+  for rev in "$@"; do
+    col=$((col + 1))
+    if [ -n "$names" ]; then name=$(echo $names | cut -d' ' -f$col); else name=$rev; fi
+    printf " %14s" "$name"
+  done
+  echo
+  sort -k1,1 -k2,2n "$work/results" | awk '
+    $1 != prev { if (prev != "") print ""; printf "%-50s", $1; prev = $1 }
+    { printf " %14s", $3 }
+    END { print "" }'
+}
@@ -82,4 +82,28 @@ mod tree_benchmarks {
             b.iter(|| sum_tree(t.clone()))
         }
     }
+
+    // Each iteration edits one element of the input, and then
+    // rebuilds and re-sums the tree, dirtying and cleaning the DCG.
+    #[bench]
+    fn benchmark_dcg_tree_edit(b: &mut Bencher) {
+        init_dcg();
+        let input = |x:usize| {
+            let mut l: List<usize> = List::nil();
+            for i in (1..100).into_iter() {
+                let l_ = List::art(cell(name_of_usize(i), l));
+                let l_ = List::name(name_of_usize(i), l_);
+                l = List::cons(if i == 50 { x } else { i }, l_)
+            }
+            l
+        };
+        let mut x = 0;
+        b.iter(|| {
+            x += 1;
+            let l = input(x);
+            let t = ns(name_of_str("tree_of_list"),
+                       ||tree_of_list::<_,_,Tree<_>,_>(Dir2::Left, l.clone()));
+            sum_tree(t)
+        })
+    }
 }
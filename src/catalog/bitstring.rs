@@ -1,3 +1,6 @@
+use adapton::engine::name_of_str;
+use adapton::engine::reflect::{Val,ReflectVal,reflect_val};
+
 /// Bit Strings are length/value pairs, so that bit strings with leading
 /// zeros aren't conflated.
 #[derive(Eq,PartialEq,Hash,Debug,Clone,Copy)]
@@ -6,6 +9,13 @@ pub struct BS {
     pub value: i64,
 }
 
+impl ReflectVal for BS {
+    fn reflect_val(&self) -> Val {
+        Val::Struct(name_of_str("BS"), vec![(name_of_str("length"), reflect_val(&self.length)),
+                                            (name_of_str("value"), reflect_val(&self.value))])
+    }
+}
+
 pub trait BitString {
     fn pow(i64, i64) -> i64;
     fn flip(i64, i64) -> i64;
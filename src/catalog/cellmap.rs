@@ -0,0 +1,157 @@
+//! Keyed input collections, with per-key dependency tracking.
+//!
+//! A `CellMap` holds one DCG cell per key, plus one cell for its set
+//! of keys.  Reading a key observes only that key's cell; changing
+//! the value at a key dirties only the readers of that key, and
+//! adding or removing a key also dirties the readers of the key set.
+//!
+//! `CellMap`s are inputs: mutate them only from the outer layer (not
+//! from within thunks), as with `set`.
+
+use std::fmt::Debug;
+use std::fmt;
+use std::hash::{Hash,Hasher};
+use std::collections::HashMap;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use adapton::engine::* ;
+
+/// A finite map from `K` to `V`, whose bindings are DCG cells.
+/// Clones share the same cells.
+pub struct CellMap<K,V> {
+  name  : Name,
+  keys  : Art<Vec<K>>,
+  cells : Rc<RefCell<HashMap<K,Art<Option<V>>>>>,
+}
+
+impl<K:'static+Hash+Eq+Debug+Clone,
+     V:'static+Hash+Eq+Debug+Clone> CellMap<K,V> {
+
+  /// Creates an empty map, whose cells are named by `name`.
+  pub fn new (name:Name) -> Self {
+    let keys = cell(name_pair(name.clone(), name_of_str("keys")), vec![]);
+    CellMap{ name:name, keys:keys, cells:Rc::new(RefCell::new(HashMap::new())) }
+  }
+
+  fn key_cell (&self, k:&K) -> Option<Art<Option<V>>> {
+    self.cells.borrow().get(k).map(|c| c.clone())
+  }
+
+  /// Returns the value at `k`, observing only the cell for `k`; for
+  /// a key without a cell, observes whether the key set contains `k`.
+  pub fn get (&self, k:&K) -> Option<V> {
+    match self.key_cell(k) {
+      Some(c) => force(&c),
+      None => {
+        // Observe the key set, for a future insertion of `k`.
+        let k = k.clone();
+        let _ = force_map(&self.keys, move |_,ks| ks.contains(&k));
+        None
+      }
+    }
+  }
+
+  /// True iff the map has a value at `k`; observes only whether `k`
+  /// has a value, and not the value itself.
+  pub fn contains_key (&self, k:&K) -> bool {
+    match self.key_cell(k) {
+      Some(c) => force_map(&c, |_,v| v.is_some()),
+      None => {
+        let k = k.clone();
+        force_map(&self.keys, move |_,ks| ks.contains(&k))
+      }
+    }
+  }
+
+  /// The keys of the map, in the order of their insertion; observes
+  /// the key set.
+  pub fn keys (&self) -> Vec<K> {
+    force(&self.keys)
+  }
+
+  /// The number of keys in the map; observes the key set.
+  pub fn len (&self) -> usize {
+    force_map(&self.keys, |_,ks| ks.len())
+  }
+
+  /// True iff the map has no keys; observes the key set.
+  pub fn is_empty (&self) -> bool {
+    self.len() == 0
+  }
+
+  /// The bindings of the map, in the order of their insertion;
+  /// observes the key set and every key.
+  pub fn entries (&self) -> Vec<(K,V)> {
+    self.keys().into_iter().filter_map(|k| {
+      let v = self.get(&k);
+      v.map(|v| (k, v))
+    }).collect()
+  }
+
+  /// Binds `k` to `v`, and returns the previous value at `k`.
+  /// Dirties the readers of `k` if its value changes, and the readers
+  /// of the key set if `k` is new.
+  pub fn insert (&mut self, k:K, v:V) -> Option<V> {
+    match self.key_cell(&k) {
+      Some(c) => {
+        let old = force(&c);
+        if old.is_none() { update(&self.keys, |ks| { ks.push(k.clone()); true }) } ;
+        set(&c, Some(v));
+        old
+      },
+      None => {
+        let count = self.cells.borrow().len();
+        let c = cell(name_pair(self.name.clone(), name_of_usize(count)), Some(v));
+        self.cells.borrow_mut().insert(k.clone(), c);
+        update(&self.keys, |ks| { ks.push(k); true });
+        None
+      }
+    }
+  }
+
+  /// Removes the value at `k`, and returns it.  Dirties the readers
+  /// of `k` and of the key set if `k` had a value.
+  pub fn remove (&mut self, k:&K) -> Option<V> {
+    match self.key_cell(k) {
+      None => None,
+      Some(c) => {
+        let old = force(&c);
+        if old.is_some() {
+          update(&self.keys, |ks| { ks.retain(|k2| k2 != k); true });
+          set(&c, None);
+        } ;
+        old
+      }
+    }
+  }
+}
+
+impl<K:Clone,V> Clone for CellMap<K,V> {
+  fn clone (&self) -> Self {
+    CellMap{ name:self.name.clone(), keys:self.keys.clone(), cells:self.cells.clone() }
+  }
+}
+
+/// Maps are identified by their name and key-set cell, so that thunks
+/// may take them as arguments.
+impl<K:Hash,V> Hash for CellMap<K,V> {
+  fn hash<H:Hasher>(&self, h:&mut H) {
+    self.name.hash(h);
+    self.keys.hash(h);
+  }
+}
+
+impl<K:PartialEq,V> PartialEq for CellMap<K,V> {
+  fn eq (&self, other:&Self) -> bool {
+    self.name == other.name && self.keys == other.keys
+  }
+}
+
+impl<K:Eq,V> Eq for CellMap<K,V> { }
+
+impl<K:Debug,V> Debug for CellMap<K,V> {
+  fn fmt (&self, f:&mut fmt::Formatter) -> fmt::Result {
+    write!(f, "CellMap({:?}, {:?})", self.name, self.keys)
+  }
+}
@@ -13,6 +13,15 @@ pub mod trie {
 #[derive(Clone,Copy,Hash,Eq,PartialEq,Debug)]
 pub enum Dir2 { Left, Right }
 
+impl reflect::ReflectVal for Dir2 {
+  fn reflect_val(&self) -> reflect::Val {
+    match *self {
+      Dir2::Left  => reflect::Val::Constr(name_of_str("Left"), vec![]),
+      Dir2::Right => reflect::Val::Constr(name_of_str("Right"), vec![]),
+    }
+  }
+}
+
 trait Invert { fn invert(&self) -> Self; }
 impl Invert for Dir2 {
   fn invert(&self) -> Self {
@@ -1103,6 +1112,40 @@ pub enum NameElse<X> {
   Else(X),
 }
 
+impl<X:Debug> reflect::ReflectVal for List<X> {
+  fn reflect_val(&self) -> reflect::Val {
+    use adapton::engine::reflect::{Val, reflect_val};
+    match *self {
+      List::Nil => Val::Constr(name_of_str("Nil"), vec![]),
+      List::Cons(ref hd, ref tl) =>
+        Val::Constr(name_of_str("Cons"), vec![reflect_val(hd), reflect_val(&**tl)]),
+      List::Tree(ref t, ref d, ref tl) =>
+        Val::Constr(name_of_str("Tree"), vec![reflect_val(&**t), reflect_val(d), reflect_val(&**tl)]),
+      List::Name(ref nm, ref tl) =>
+        Val::Constr(name_of_str("Name"), vec![Val::Name(nm.clone()), reflect_val(&**tl)]),
+      List::Art(ref a) =>
+        Val::Constr(name_of_str("Art"), vec![reflect_val(a)]),
+    }
+  }
+}
+
+impl<X:Debug> reflect::ReflectVal for Tree<X> {
+  fn reflect_val(&self) -> reflect::Val {
+    use adapton::engine::reflect::{Val, Const, reflect_val};
+    match *self {
+      Tree::Nil => Val::Constr(name_of_str("Nil"), vec![]),
+      Tree::Leaf(ref x) => Val::Constr(name_of_str("Leaf"), vec![reflect_val(x)]),
+      Tree::Bin(ref lev, ref l, ref r) =>
+        Val::Constr(name_of_str("Bin"), vec![Val::Const(Const::Nat(*lev)), reflect_val(&**l), reflect_val(&**r)]),
+      Tree::Name(ref nm, ref lev, ref l, ref r) =>
+        Val::Constr(name_of_str("Name"), vec![Val::Name(nm.clone()), Val::Const(Const::Nat(*lev)),
+                                              reflect_val(&**l), reflect_val(&**r)]),
+      Tree::Art(ref a) =>
+        Val::Constr(name_of_str("Art"), vec![reflect_val(a)]),
+    }
+  }
+}
+
 impl<X:'static+Debug+Hash+PartialEq+Eq+Clone> ListIntro<X> for List<X>
 {
   fn nil  ()                 -> Self { List::Nil }
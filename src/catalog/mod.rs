@@ -13,4 +13,6 @@
 
 pub mod collections ;
 pub mod bitstring ;
+pub mod cellmap ;
+pub mod testing ;
 mod trie ;
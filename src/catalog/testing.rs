@@ -0,0 +1,87 @@
+//! Testing harnesses for incremental programs.
+//!
+//! The harness here runs a program under both the `Naive` engine and
+//! the `DCG` engine, over a common sequence of input edits, and checks
+//! that change propagation in the DCG is from-scratch consistent (see
+//! also `test_engine_alternation` in `collections`).
+
+use std::fmt::Debug;
+
+use adapton::engine::* ;
+
+/// A sequence of input edits, for use with `differential`.  The
+/// harness builds and edits a separate input for each engine, so
+/// that each input's articulations belong to the engine that uses it.
+pub trait EditScript {
+  /// The input of the program under test.
+  type Input ;
+  /// Build the initial input, under the current engine.
+  fn init (&self) -> Self::Input ;
+  /// The number of edits in the script.
+  fn edit_count (&self) -> usize ;
+  /// Apply edit number `step` (counting from zero) to the input, under the current engine.
+  fn edit (&self, step:usize, input:Self::Input) -> Self::Input ;
+}
+
+/// Runs `program` under the `Naive` engine and under a fresh `DCG`
+/// engine, first on the initial input of `edit_script`, and then
+/// after each of its edits.  The naive run recomputes from scratch
+/// each time; the DCG run reuses its graph across edits, and hence
+/// exercises change propagation.
+///
+/// Panics if the two runs' outputs differ, after printing the first
+/// diverging edit and a dump of the DCG.  Outputs are compared with
+/// `PartialEq`, so they should not contain articulations (an `Art`
+/// from one engine is never equal to one from the other).
+///
+/// Restores the engine in use before the call upon returning, or
+/// panicking.
+pub fn differential<I,O,P,E>(program:P, edit_script:E)
+  where I:Clone, O:Debug+PartialEq,
+        P:Fn(I) -> O,
+        E:EditScript<Input=I>
+{
+  let _outer = RestoreEngine(Some(manage::init_dcg()));
+  let mut dcg = manage::init_naive();
+
+  let mut naive_input = edit_script.init();
+  manage::use_engine(dcg);
+  let mut dcg_input = edit_script.init();
+  dcg = manage::init_naive();
+
+  for step in 0..(edit_script.edit_count() + 1) {
+    assert!(manage::engine_is_naive());
+    if step > 0 { naive_input = edit_script.edit(step - 1, naive_input) } ;
+    let naive_out = program(naive_input.clone());
+
+    manage::use_engine(dcg);
+    assert!(manage::engine_is_dcg());
+    if step > 0 { dcg_input = edit_script.edit(step - 1, dcg_input) } ;
+    let dcg_out = program(dcg_input.clone());
+
+    if naive_out != dcg_out {
+      match step {
+        0    => println!("differential: outputs diverge on the initial input"),
+        step => println!("differential: outputs diverge after edit {} (of {})",
+                         step - 1, edit_script.edit_count()),
+      } ;
+      println!("differential: naive output: {:?}", naive_out);
+      println!("differential: DCG output:   {:?}", dcg_out);
+      if let Some(dcg) = reflect::dcg_reflect_now() {
+        println!("differential: DCG:\n{}", reflect::string_of_dcg(&dcg));
+      } ;
+      panic!("differential: DCG output differs from naive output")
+    } ;
+    dcg = manage::init_naive();
+  } ;
+}
+
+/// Switches back to an engine when dropped, including when unwinding
+/// from a panic (e.g., of the program under test).
+struct RestoreEngine(Option<Engine>);
+
+impl Drop for RestoreEngine {
+  fn drop(&mut self) {
+    if let Some(engine) = self.0.take() { drop(manage::use_engine(engine)) }
+  }
+}
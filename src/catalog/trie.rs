@@ -23,6 +23,21 @@ pub enum Trie<X> {
     Art(Art<Trie<X>>),
 }
 
+impl<X:Debug> reflect::ReflectVal for Trie<X> {
+    fn reflect_val(&self) -> reflect::Val {
+        use adapton::engine::reflect::{Val, reflect_val};
+        match *self {
+            Trie::Nil(ref bs) => Val::Constr(name_of_str("Nil"), vec![reflect_val(bs)]),
+            Trie::Leaf(ref bs, ref x) => Val::Constr(name_of_str("Leaf"), vec![reflect_val(bs), reflect_val(x)]),
+            Trie::Bin(ref bs, ref l, ref r) =>
+                Val::Constr(name_of_str("Bin"), vec![reflect_val(bs), reflect_val(&**l), reflect_val(&**r)]),
+            Trie::Root(ref meta, ref t) => Val::Constr(name_of_str("Root"), vec![reflect_val(meta), reflect_val(&**t)]),
+            Trie::Name(ref nm, ref t) => Val::Constr(name_of_str("Name"), vec![Val::Name(nm.clone()), reflect_val(&**t)]),
+            Trie::Art(ref a) => Val::Constr(name_of_str("Art"), vec![reflect_val(a)]),
+        }
+    }
+}
+
 pub const PLACEMENT_SEED: u64 = 42;
 
 /// Metadata held by the root node.
@@ -28,35 +28,115 @@
 use core::any::TypeId;
 use core::marker::PhantomData;
 
-use std::cell::RefCell;
+use std::cell::{Cell,RefCell};
 use std::collections::HashMap;
 use std::env;
 use std::fmt::Debug;
 use std::fmt::{Formatter,Result};
 use std::fmt;
-use std::hash::{Hash,Hasher};
+use std::hash::{Hash,Hasher,BuildHasherDefault};
 use std::collections::hash_map::DefaultHasher;
 use std::mem::replace;
 use std::mem::transmute;
-use std::rc::Rc;
+use std::rc::{Rc,Weak};
 use std::fmt::Write;
 
 use macros::{ProgPt};
 
 thread_local!(static GLOBALS: RefCell<Globals> = RefCell::new(Globals{engine:Engine::Naive}));
-thread_local!(static UNIT_NAME: Name = Name{ hash:0, symbol: Rc::new(NameSym::Unit) });
+thread_local!(static UNIT_NAME: Name = Name{ hash:0, symbol: intern_namesym(NameSym::Unit) });
+
+struct TraceSt {
+  /// Registered listeners, with the ids that `dcg_listener_add` gave them.
+  listeners:Vec<(usize, Rc<RefCell<reflect::trace::TraceListener>>)>,
+  next_id:usize,
+  /// The forest builders of (nested calls to) `dcg_reflect_begin`,
+  /// innermost last, and their listener ids.
+  forests:Vec<(usize, Rc<RefCell<reflect::trace::TraceForest>>)>,
+}
+
+// When this set of listeners is non-empty, the engine will report its DCG effects to them.
+thread_local!(static TRACES: RefCell<TraceSt> = RefCell::new( TraceSt{ listeners:vec![], next_id:0, forests:vec![] } ));
+
+/// A snapshot of the registered listeners.  The engine calls them
+/// through this snapshot, and not while `TRACES` is borrowed, so that
+/// a listener may add or remove listeners (including itself).
+fn dcg_listeners() -> Vec<Rc<RefCell<reflect::trace::TraceListener>>> {
+  TRACES.with(|tr| {
+    tr.borrow().listeners.iter().map(|&(_, ref l)| l.clone()).collect()
+  })
+}
 
-struct TraceSt { stack:Vec<Box<Vec<reflect::trace::Trace>>>, }
+/// A hash function for names, locations and structural identities;
+/// see `manage::use_hash_fn`.  Each is deterministic across runs, so
+/// that hashes may be persisted.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Hash)]
+pub enum HashFn {
+  /// A fast, non-cryptographic hash; see `FxHasher`.  The default.
+  Fx,
+  /// SipHash, with fixed keys, as by `std`'s `DefaultHasher`.
+  Sip,
+}
 
-/// When this option is set to some, the engine will record a trace of its DCG effects.
-thread_local!(static TRACES: RefCell<Option<TraceSt>> = RefCell::new( None ));
+// The hash function in use, and whether it has hashed anything yet;
+// see `manage::use_hash_fn`.
+thread_local!(static HASH_FN: Cell<(HashFn, bool)> = Cell::new((HashFn::Fx, false)));
 
 fn my_hash<T>(obj: T) -> u64
   where T: Hash
 {
-  let mut hasher = DefaultHasher::new();
-  obj.hash(&mut hasher);
-  hasher.finish()
+  let hash_fn = HASH_FN.with(|h| {
+    let (hash_fn, used) = h.get();
+    if !used { h.set((hash_fn, true)) } ;
+    hash_fn
+  });
+  match hash_fn {
+    HashFn::Fx => {
+      let mut hasher = FxHasher::default();
+      obj.hash(&mut hasher);
+      hasher.finish()
+    },
+    HashFn::Sip => {
+      let mut hasher = DefaultHasher::new();
+      obj.hash(&mut hasher);
+      hasher.finish()
+    },
+  }
+}
+
+const FX_SEED : u64 = 0x51_7c_c1_b7_27_22_0a_95 ;
+
+/// A fast, non-cryptographic hasher, in the style of FxHash (the
+/// hasher of Firefox and `rustc`).  It mixes in each word with a
+/// rotate, an xor and a multiply.  Its hashes are deterministic, and
+/// do not depend on the platform's byte order or word size.
+#[derive(Debug,Clone,Copy,Default)]
+pub struct FxHasher { hash : u64 }
+
+impl FxHasher {
+  #[inline]
+  fn add_to_hash(&mut self, word:u64) {
+    self.hash = (self.hash.rotate_left(5) ^ word).wrapping_mul(FX_SEED)
+  }
+}
+
+impl Hasher for FxHasher {
+  fn write(&mut self, bytes:&[u8]) {
+    for chunk in bytes.chunks(8) {
+      let mut word = [0u8; 8];
+      word[..chunk.len()].copy_from_slice(chunk);
+      self.add_to_hash(u64::from_le_bytes(word))
+    }
+    // The last chunk is padded with zeros; so that (e.g.) `b"a"` and
+    // `b"a\0"` differ, mix in the length too.
+    self.add_to_hash(bytes.len() as u64)
+  }
+  fn write_u8   (&mut self, i:u8)    { self.add_to_hash(i as u64) }
+  fn write_u16  (&mut self, i:u16)   { self.add_to_hash(i as u64) }
+  fn write_u32  (&mut self, i:u32)   { self.add_to_hash(i as u64) }
+  fn write_u64  (&mut self, i:u64)   { self.add_to_hash(i) }
+  fn write_usize(&mut self, i:usize) { self.add_to_hash(i as u64) }
+  fn finish(&self) -> u64 { self.hash }
 }
 
 /// Reflects the DCG engine, including both the effects of the
@@ -82,9 +162,12 @@ fn my_hash<T>(obj: T) -> u64
 pub mod reflect {
   pub use reflect::*;
   pub use parse_val;
+  pub use parse_val::{parse_name,parse_path,parse_loc};
 
   use std::fmt::{Write};
-  use super::{TraceSt,TRACES,GLOBALS,Engine};
+  use std::cell::RefCell;
+  use std::rc::Rc;
+  use super::{TRACES,GLOBALS,Engine};
   use adapton::engine::Name;
 
   /// See doc for `write_name`. Returns this output as a string.
@@ -101,13 +184,15 @@ pub mod reflect {
   }
 
   /// Write a concise human-readable version of the name (not the
-  /// verbose, machine-parsable `Debug` version).
+  /// verbose, machine-parsable `Debug` version).  `parse_name`
+  /// parses it back.
   pub fn write_name<W:Write> (w:&mut W, n:&Name) {
     super::write_namesym(w, &n.symbol).unwrap();
   }
 
   /// Write a concise human-readable version of the path (not the
-  /// verbose, machine-parsable `Debug` version).
+  /// verbose, machine-parsable `Debug` version).  `parse_path`
+  /// parses it back.
   pub fn write_path<W:Write> (w:&mut W, p:&Path) {
     write!(w, "__").unwrap(); // Underscores are valid in CSS class names
     for n in p.iter() {
@@ -117,13 +202,113 @@ pub mod reflect {
   }
 
   /// Write a concise human-readable version of the location (not the
-  /// verbose, machine-parsable `Debug` version).  
+  /// verbose, machine-parsable `Debug` version).  `parse_loc` parses
+  /// it back.
   pub fn write_loc<W:Write> (w:&mut W, l:&Loc)  {
     write_path(w, &l.path);
     write!(w, "_").unwrap(); // Underscores are valid in CSS class names
     write_name(w, &l.name);
   }
-  
+
+  /// See doc for `write_dcg`. Returns this output as a string.
+  pub fn string_of_dcg (dcg:&DCG) -> String {
+    let mut output = String::from("");  write_dcg(&mut output, dcg); output
+  }
+
+  /// Write a canonical text version of the (reflected) DCG, one line
+  /// per node and per edge: its current path; its nodes, sorted by
+  /// their locations (as `write_loc` writes them), each followed by
+  /// its successor and predecessor edges, sorted likewise; and its
+  /// stack frames, from the bottom of the stack.  The entries of map
+  /// and set values are sorted by their `Debug` text, rather than
+  /// written in the (hash) order of the map or set.
+  ///
+  /// For DCGs whose locations are all named explicitly, the output
+  /// depends only on the structure and values of the DCG, and not on
+  /// the order of its allocations, so that golden tests may assert it,
+  /// and so that equivalent DCGs have equal output.  However, the
+  /// locations of structural thunks and cells (and of `intern`
+  /// nodes) are named by a hash (as `#<hash>`), and this hash varies
+  /// with the hash function in use (see `manage::use_hash_fn`), and
+  /// with any rehashing after a collision; so for those locations,
+  /// the output (and the order of the nodes) may vary too.
+  pub fn write_dcg<W:Write> (w:&mut W, dcg:&DCG) {
+    fn effect_str (eff:&Effect) -> &'static str {
+      match *eff { Effect::Force => "force", Effect::Alloc => "alloc" }
+    }
+    fn sorted_val (v:&Val) -> Val {
+      fn sorted_vals (vs:&Vec<Val>) -> Vec<Val> { vs.iter().map(sorted_val).collect() }
+      match *v {
+        Val::Constr(ref n, ref vs) => Val::Constr(n.clone(), sorted_vals(vs)),
+        Val::Tuple(ref vs) => Val::Tuple(sorted_vals(vs)),
+        Val::Vec(ref vs) => Val::Vec(sorted_vals(vs)),
+        Val::Struct(ref n, ref fs) =>
+          Val::Struct(n.clone(), fs.iter().map(|&(ref f, ref v)| (f.clone(), sorted_val(v))).collect()),
+        Val::Map(ref es) => {
+          let mut es : Vec<(String, (Val, Val))> = es.iter().map(|&(ref k, ref v)| {
+            let k = sorted_val(k);
+            (format!("{:?}", k), (k, sorted_val(v)))
+          }).collect();
+          es.sort_by(|a, b| a.0.cmp(&b.0));
+          Val::Map(es.into_iter().map(|(_, e)| e).collect())
+        },
+        Val::Set(ref vs) => {
+          let mut vs : Vec<(String, Val)> =
+            vs.iter().map(|v| { let v = sorted_val(v); (format!("{:?}", v), v) }).collect();
+          vs.sort_by(|a, b| a.0.cmp(&b.0));
+          Val::Set(vs.into_iter().map(|(_, v)| v).collect())
+        },
+        Val::Art(ref l, ArtContent::Val(ref v)) => Val::Art(l.clone(), ArtContent::Val(Rc::new(sorted_val(v)))),
+        Val::Art(ref l, ArtContent::Comp(Some(ref v))) => Val::Art(l.clone(), ArtContent::Comp(Some(Rc::new(sorted_val(v))))),
+        ref v => v.clone(),
+      }
+    }
+    fn write_succs<W:Write> (w:&mut W, succs:&Vec<Succ>) {
+      let mut succs : Vec<(String, &Succ)> =
+        succs.iter().map(|succ| (string_of_loc(&succ.loc), succ)).collect();
+      succs.sort_by(|a, b| (&a.0, effect_str(&a.1.effect)).cmp(&(&b.0, effect_str(&b.1.effect))));
+      for (loc, succ) in succs {
+        writeln!(w, "  {}{} {} = {:?}", if succ.dirty { "dirty " } else { "" },
+                 effect_str(&succ.effect), loc, sorted_val(&succ.value)).unwrap();
+      }
+    }
+    fn write_preds<W:Write> (w:&mut W, preds:&Vec<Pred>) {
+      let mut preds : Vec<(String, &'static str)> =
+        preds.iter().map(|pred| (string_of_loc(&pred.loc), effect_str(&pred.effect))).collect();
+      preds.sort();
+      for (loc, eff) in preds {
+        writeln!(w, "  pred {} {}", eff, loc).unwrap();
+      }
+    }
+    writeln!(w, "path {}", string_of_path(&dcg.path)).unwrap();
+    let mut nodes : Vec<(String, &Node)> =
+      dcg.table.iter().map(|(loc, nd)| (string_of_loc(loc), nd)).collect();
+    nodes.sort_by(|a, b| a.0.cmp(&b.0));
+    for (loc, nd) in nodes {
+      match *nd {
+        Node::Comp(ref nd) => {
+          match nd.value {
+            None => writeln!(w, "comp {} {:?}", loc, nd.prog_pt).unwrap(),
+            Some(ref v) => writeln!(w, "comp {} {:?} = {:?}", loc, nd.prog_pt, sorted_val(v)).unwrap(),
+          } ;
+          write_succs(w, &nd.succs);
+          write_preds(w, &nd.preds);
+        },
+        Node::Ref(ref nd) => {
+          writeln!(w, "ref {} = {:?}", loc, sorted_val(&nd.value)).unwrap();
+          write_preds(w, &nd.preds);
+        },
+        Node::Pure(ref nd) => {
+          writeln!(w, "pure {} = {:?}", loc, sorted_val(&nd.value)).unwrap();
+        },
+      }
+    } ;
+    for frame in dcg.stack.iter() {
+      writeln!(w, "frame {}", string_of_loc(&frame.loc)).unwrap();
+      write_succs(w, &frame.succs);
+    }
+  }
+
   /// Reflect the DCG's internal structure now.  Does not reflect any
   /// engine effects over this DCG (e.g., no cleaning or dirtying),
   /// just the _program effects_ recorded by the DCG's structure.
@@ -138,69 +323,86 @@ pub mod reflect {
     })
   }
 
-  /// Begin recording (reflections of) DCG effects.  See `dcg_reflect_end()`.
-  pub fn dcg_reflect_begin() {
-    TRACES.with(|tr| { 
-      let check = match *tr.borrow() {
-        None => true,
-        Some(_) => false };
-      if check { 
-        *tr.borrow_mut() = Some(TraceSt{stack:vec![Box::new(vec![])]})
-      } else { 
-        panic!("cannot currently nest calls to dcg_reflect_begin().")
+  /// Register a listener for (reflections of) DCG effects; returns
+  /// an id for use with `dcg_listener_remove`.  Unlike
+  /// `dcg_reflect_begin`, the listener observes each effect as the
+  /// engine performs it, and need not store anything.
+  pub fn dcg_listener_add(listener:Rc<RefCell<trace::TraceListener>>) -> usize {
+    TRACES.with(|tr| {
+      let tr = &mut *tr.borrow_mut();
+      let id = tr.next_id;
+      tr.next_id += 1;
+      tr.listeners.push((id, listener));
+      id
+    })
+  }
+
+  /// Unregister the listener with the given id; returns it, if it
+  /// was registered.  See `dcg_listener_add()`.
+  pub fn dcg_listener_remove(id:usize) -> Option<Rc<RefCell<trace::TraceListener>>> {
+    TRACES.with(|tr| {
+      let tr = &mut *tr.borrow_mut();
+      match tr.listeners.iter().position(|&(id2, _)| id2 == id) {
+        None => None,
+        Some(idx) => Some(tr.listeners.remove(idx).1),
       }
     })
   }
+
+  /// Begin recording (reflections of) DCG effects.  See `dcg_reflect_end()`.
+  ///
+  /// Calls may nest: Each call begins a region that ends with the
+  /// matching call to `dcg_reflect_end()`.  The effects of an inner
+  /// region are recorded by it, and by every enclosing region.
+  pub fn dcg_reflect_begin() {
+    let forest = Rc::new(RefCell::new(trace::TraceForest::new()));
+    let id = dcg_listener_add(forest.clone());
+    TRACES.with(|tr| tr.borrow_mut().forests.push((id, forest)))
+  }
   
   /// Stop recording (reflections of) DCG effects, and return them as a
   /// forrest (of DCG traces).  See `dcg_reflect_begin()`.
+  ///
+  /// Ends the innermost region, and returns only its effects; any
+  /// enclosing regions continue recording.
   pub fn dcg_reflect_end() -> Vec<trace::Trace> {
-    TRACES.with(|tr| { 
-      let traces = match *tr.borrow_mut() {
-        None => panic!("dcg_reflect_end() without a corresponding dcg_reflect_begin()."),
-        Some(ref mut tr) => { 
-          // Assert that dcg_effect_(begin/end) are not mismatched.
-          assert_eq!(tr.stack.len(), 1);
-          tr.stack.pop()
-        }
-      };
-      match traces {
-        None => unreachable!(),
-        Some(traces) => {
-          *tr.borrow_mut() = None;
-          *traces
+    let forest = TRACES.with(|tr| tr.borrow_mut().forests.pop());
+    match forest {
+      None => panic!("dcg_reflect_end() without a corresponding dcg_reflect_begin()."),
+      Some((id, forest)) => {
+        drop(dcg_listener_remove(id));
+        match Rc::try_unwrap(forest) {
+          Ok(forest) => forest.into_inner().into_traces(),
+          // Something else (e.g., a listener) still holds the forest;
+          // copy the traces out, and leave it to them.
+          Err(forest) => forest.borrow().clone().into_traces(),
         }
       }
-    })
+    }
   }
 }
 use reflect::Reflect;
+use persist::{PVec,PMap};
 
 
 //#[macro_export]
 macro_rules! dcg_effect_begin {
   ( $eff:expr, $loc:expr, $succ:expr, $has_extent:expr ) => {{ 
-    /// The beginning of an effect, with an option extent (nested effects)
-    TRACES.with(|tr| {
-      match *tr.borrow_mut() {
-        None => (),
-        // Some ==> We are building a trace
-        Some(ref mut ts) => {            
-          match ts.stack.last_mut() {
-            None => unreachable!(),
-            Some(ref mut ts) => { 
-              ts.push( reflect::trace::Trace{
-                extent: Box::new(vec![]),
-                effect:$eff,
-                edge: reflect::trace::Edge{
-                  loc:  $loc.reflect(),
-                  succ: $succ.reflect(),
-                }})}};
-          if $has_extent {
-            ts.stack.push(Box::new(vec![]))
-          } else { }
-        }
-      }})
+    // The beginning of an effect, with an option extent (nested effects)
+    let listeners = dcg_listeners();
+    if listeners.len() > 0 {
+      // Non-empty ==> Someone is listening
+      let effect = $eff;
+      let edge = reflect::trace::Edge{
+        loc:  $loc.reflect(),
+        succ: $succ.reflect(),
+      };
+      for l in listeners.iter() {
+        let l = &mut *l.borrow_mut();
+        l.on_begin(&effect, &edge);
+        if $has_extent { } else { l.on_end() }
+      }
+    }
   }}
   ;
   ( $eff:expr, $loc:expr, $succ:expr ) => {{
@@ -211,34 +413,16 @@ macro_rules! dcg_effect_begin {
 //#[macro_export]
 macro_rules! dcg_effect_end {
   () => {{ 
-    /// The end of an effects' extent. Operationally, the traces at
-    /// the top of the stack are popped; they become the extent of the
-    /// trace at the end (top) of the second top-most sequence of
-    /// traces.
-    TRACES.with(|tr| {
-      match *tr.borrow_mut() {
-        None => (),
-        Some(ref mut tr) => {
-          let trs = tr.stack.pop(); 
-          match (trs, tr.stack.last_mut()) {
-            (None, _) => unreachable!(),
-            (_, None) => unreachable!(),
-            (Some(parent_extent), Some(trs)) => 
-              match trs.last_mut() {
-                None => unreachable!(),
-                Some(parent) => { assert_eq!(parent.extent.len(), 0);
-                                  parent.extent = parent_extent }
-              }
-          }
-        }
-      }
-    })
+    // The end of an effects' extent.
+    for l in dcg_listeners().iter() {
+      l.borrow_mut().on_end()
+    }
   }}
 }
 
 macro_rules! dcg_effect {
   ( $eff:expr, $loc:expr, $succ:expr ) => {{ 
-    /// An effect without an extent (without nested effects)
+    // An effect without an extent (without nested effects)
     dcg_effect_begin!($eff, $loc, $succ, false)
   }}
 }
@@ -257,11 +441,20 @@ macro_rules! current_loc {
 /// different content over time, it describes *where* incremental
 /// changing is occurring, relative to other (unaffected) parts of
 /// data structures or computations.
-#[derive(PartialEq,Eq,Clone)]
+///
+/// Name symbols are interned, so names compare by their hash and the
+/// address of their symbol, and not by traversing the symbol.
+#[derive(Clone)]
 pub struct Name {
   hash : u64, // hash of symbol
-  symbol : Rc<NameSym>,
+  symbol : Rc<NameSym>, // interned
 }
+impl PartialEq for Name {
+  fn eq(&self, other:&Self) -> bool {
+    self.hash == other.hash && Rc::ptr_eq(&self.symbol, &other.symbol)
+  }
+}
+impl Eq for Name { }
 impl Debug for Name {
   fn fmt(&self, f:&mut Formatter) -> Result { self.symbol.fmt(f) }
 }
@@ -270,14 +463,25 @@ impl Hash for Name {
     self.hash.hash(state)
   }
 }
+impl reflect::ReflectVal for Name {
+  fn reflect_val(&self) -> reflect::Val { reflect::Val::Name(self.clone()) }
+}
 
 // Each location identifies a node in the DCG.
-#[derive(PartialEq,Eq,Clone)]
+#[derive(Clone)]
 struct Loc {
   hash : u64, // hash of (path,id)
   path : Rc<Path>,
   id   : Rc<ArtId>,
+  node : NodeId, // not part of the location's identity; see `DCG::loc_of_id`
 }
+impl PartialEq for Loc {
+  fn eq(&self, other:&Loc) -> bool {
+    ::std::ptr::eq(self, other) ||
+      (self.hash == other.hash && self.path == other.path && self.id == other.id)
+  }
+}
+impl Eq for Loc { }
 impl Debug for Loc {
   fn fmt(&self, f:&mut Formatter) -> Result {
     //write!(f,"{:?}*{:?}",self.path,self.id)
@@ -289,6 +493,13 @@ impl Hash for Loc {
     self.hash.hash(state)
   }
 }
+
+/// The index of a node in the DCG's arena (see `DCG::nodes`).
+#[derive(PartialEq,Eq,Hash,Clone,Copy,Debug)]
+struct NodeId(u32);
+impl NodeId {
+  fn index(self) -> usize { self.0 as usize }
+}
 impl reflect::Reflect<reflect::Loc> for Loc {
   fn reflect(&self) -> reflect::Loc {
     reflect::Loc {
@@ -319,13 +530,17 @@ impl Debug for ArtId {
 }
 
 /// Flags control runtime behavior of the DCG.
-#[derive(Debug)]
+#[derive(Debug,Clone)]
 pub struct Flags {
   pub use_purity_optimization : bool,
   /// Ignore the `Nominal` `ArtIdChoice`, and use `Structural` behavior instead
   pub ignore_nominal_use_structural : bool, 
   /// After each Adapton operation, check that the DCG is well-formed
   pub check_dcg_is_wf : bool, 
+  /// On each cache hit for a thunk, rerun its producer in a throwaway
+  /// context (recording no edges), and panic if the rerun's result
+  /// differs from the cached one.  Slow; for testing.
+  pub verify_cache_hits : bool,
   /// Within each well-formedness check, write the DCG to the local filesystem
   pub write_dcg : bool, 
   /// Deprecated: At certain points in the Engine's code, write state changes as graph-movie output
@@ -352,7 +567,9 @@ pub enum Engine {
 #[derive(Debug)]
 pub struct DCG {
   pub flags : Flags, // public because I dont want to write / design abstract accessors
-  table : HashMap<Rc<Loc>, Box<GraphNode>>,
+  nodes : PVec<Rc<GraphNode>>, // arena of nodes, indexed by `NodeId`; shared with forks (see `DCG::clone`)
+  locs  : PVec<Rc<Loc>>,       // location of each node, indexed by `NodeId`
+  ids   : PMap<Rc<Loc>, NodeId, BuildHasherDefault<FxHasher>>, // consulted only to allocate (or resolve foreign locations)
   stack : Vec<Frame>,
   path  : Rc<Path>,
   //cnt   : Cnt,
@@ -365,7 +582,7 @@ impl reflect::Reflect<reflect::DCG> for DCG {
     reflect::DCG{
       table:{
         let mut table = HashMap::new();
-        for (loc, gn) in self.table.iter() {
+        for (loc, gn) in self.table() {
           let _ = table.insert(loc.reflect(), gn.reflect());
         }; table
       },
@@ -377,10 +594,127 @@ impl reflect::Reflect<reflect::DCG> for DCG {
   }
 }
 
-impl Hash  for     DCG { fn hash<H>(&self, _state: &mut H) where H: Hasher { unimplemented!() }}
+// ----------- Node arena:
+//
+// Each node lives in the arena `nodes`, at the index given by its
+// location's `node` field.  Locations in the DCG are canonical: each
+// node has one `Rc<Loc>`, namely `locs[id]`, and every edge to the
+// node holds (a clone of) it.  Hence, following an edge indexes the
+// arena directly, and the map `ids` is consulted only when allocating
+// (or when resolving a location from elsewhere, e.g., from an older
+// DCG; see `canonical_loc`).
+//
+// Forks of a DCG (see `DCG::clone`) share their nodes, locations and
+// location map.  These are persistent (see `persist`): a fork takes
+// O(1) time, and each write copies O(log n) of the structure, along
+// with the written node, on its first write.
+impl DCG {
+  /// The nodes of the DCG, with their locations, in allocation order.
+  fn table<'r>(&'r self) -> impl Iterator<Item=(&'r Rc<Loc>, &'r GraphNode)> + 'r {
+    self.locs.iter().zip(self.nodes.iter().map(|node| &**node))
+  }
+
+  /// The location of `(path, id)`: the canonical location of its node,
+  /// if any; otherwise, a fresh location, for the next node of the
+  /// arena (which `insert_node` must allocate before any other).
+  fn loc_of_id(&self, path:Rc<Path>, id:Rc<ArtId>) -> Rc<Loc> {
+    let hash = my_hash(&(&path,&id));
+    let loc = Loc{path:path, id:id, hash:hash, node:NodeId(self.nodes.len() as u32)} ;
+    match self.ids.get(&loc) {
+      Some(&node) => self.locs[node.index()].clone(),
+      None => Rc::new(loc),
+    }
+  }
+
+  fn node_id(&self, loc:&Rc<Loc>) -> Option<NodeId> {
+    let i = loc.node.index();
+    if i < self.locs.len() && Rc::ptr_eq(&self.locs[i], loc) { Some(loc.node) }
+    else { self.ids.get(&**loc).cloned() }
+  }
+
+  /// The canonical location of `loc`; panics if it has no node.
+  fn canonical_loc(&self, loc:&Rc<Loc>) -> Rc<Loc> {
+    match self.node_id(loc) {
+      Some(node) => self.locs[node.index()].clone(),
+      None => panic!("dangling pointer: {:?}", loc),
+    }
+  }
+
+  fn node(&self, loc:&Rc<Loc>) -> Option<&GraphNode> {
+    match self.node_id(loc) {
+      Some(node) => Some(&*self.nodes[node.index()]),
+      None => None,
+    }
+  }
+
+  /// The node at `loc`, for writing; copies it first if it is shared
+  /// with a fork.
+  fn node_mut(&mut self, loc:&Rc<Loc>) -> Option<&mut (GraphNode + 'static)> {
+    match self.node_id(loc) {
+      Some(node) => {
+        let node = self.nodes.get_mut(node.index()).unwrap() ;
+        if Rc::get_mut(node).is_none() {
+          *node = node.copy()
+        } ;
+        Rc::get_mut(node)
+      },
+      None => None,
+    }
+  }
+
+  /// Replaces the node at `loc`, or allocates it, if `loc` is fresh
+  /// (see `loc_of_id`).
+  fn insert_node(&mut self, loc:&Rc<Loc>, node:Rc<GraphNode>) {
+    match self.node_id(loc) {
+      Some(id) => { *self.nodes.get_mut(id.index()).unwrap() = node },
+      None => {
+        assert_eq!(loc.node.index(), self.nodes.len());
+        self.ids.insert(loc.clone(), loc.node);
+        self.locs.push(loc.clone());
+        self.nodes.push(node)
+      }
+    }
+  }
+}
+
+// Hashing and equality are by identity: two DCGs are equal when they
+// share every node, as a fork and its original do until either one
+// changes.  (Equal DCGs are equivalent, but equivalent DCGs may be
+// unequal.)
+impl Hash  for     DCG {
+  fn hash<H>(&self, state: &mut H) where H: Hasher {
+    self.nodes.len().hash(state);
+    self.locs.iter().fold(0, |h, loc| h ^ loc.hash).hash(state)
+  }
+}
 impl Eq    for     DCG { }
-impl PartialEq for DCG { fn eq(&self, _other:&Self) -> bool { unimplemented!() } }
-impl Clone for     DCG { fn clone(&self) -> Self { unimplemented!() } }
+impl PartialEq for DCG {
+  fn eq(&self, other:&Self) -> bool {
+    self.stack == other.stack &&
+      self.path == other.path &&
+      self.nodes.len() == other.nodes.len() &&
+      self.nodes.iter().zip(other.nodes.iter()).all(|(n1, n2)| Rc::ptr_eq(n1, n2))
+  }
+}
+/// Forks the DCG, in O(1) time (ignoring the stack, which is empty
+/// outside of thunks): the fork and the original share their nodes,
+/// and each copies a node (and O(log n) of the arena) on its first
+/// write to it, leaving the other untouched.  See
+/// `manage::fork_engine`.
+impl Clone for     DCG {
+  fn clone(&self) -> Self {
+    DCG{
+      flags : self.flags.clone(),
+      nodes : self.nodes.clone(),
+      locs  : self.locs.clone(),
+      ids   : self.ids.clone(),
+      stack : self.stack.clone(),
+      path  : self.path.clone(),
+      dcg_count : self.dcg_count,
+      dcg_hash  : self.dcg_hash,
+    }
+  }
+}
 
 /// Name symbols.
 /// 
@@ -390,26 +724,188 @@ impl Clone for     DCG { fn clone(&self) -> Self { unimplemented!() } }
 /// 
 /// For a general semantics of symbols, see Chapter 31 of PFPL 2nd
 /// Edition. Harper 2016: http://www.cs.cmu.edu/~rwh/pfpl
-#[derive(Hash,PartialEq,Eq,Clone,Debug)]
+///
+/// Symbols are hash-consed (see `intern_namesym`), so the `Rc`
+/// sub-symbols of a `Pair` or fork are compared and hashed by
+/// pointer, and not by their content.
+#[derive(Clone,Debug)]
 enum NameSym {
   Unit,           // Unit value for name symbols
-  Hash64,        // Hashes (for structural names); hash stored in name struct
-  String(String), // Strings encode globally-unique symbols.
+  Hash64(u64),    // Hashes (for structural names); also stored in name struct
+  String(NameStr), // Strings encode globally-unique symbols.
   Usize(usize),   // USizes encode globally-unique symbols.
   Isize(isize),   // USizes encode globally-unique symbols.
+  U64(u64),       // U64s encode globally-unique symbols.
+  Bytes(Vec<u8>), // Byte strings encode globally-unique symbols.
   Pair(Rc<NameSym>,Rc<NameSym>), // A pair of unique symbols, interpeted as a symbol, is unique
   ForkL(Rc<NameSym>), // Left projection of a unique symbol is unique
   ForkR(Rc<NameSym>), // Right projection of a unique symbol is unique
 }
 
+/// The string of a string symbol; static strings are not copied.
+/// Equality, hashing and `Debug` are those of the string content, so
+/// a `Static` and an `Owned` string with equal content are the same
+/// symbol.
+#[derive(Clone)]
+enum NameStr {
+  Static(&'static str),
+  Owned(String),
+}
+impl NameStr {
+  fn as_str(&self) -> &str {
+    match *self { NameStr::Static(s) => s, NameStr::Owned(ref s) => s }
+  }
+}
+impl Debug for NameStr {
+  fn fmt(&self, f:&mut Formatter) -> Result { self.as_str().fmt(f) }
+}
+
+fn namesym_ptr(n:&Rc<NameSym>) -> usize { &**n as *const NameSym as usize }
+
+impl Hash for NameSym {
+  fn hash<H>(&self, state: &mut H) where H: Hasher {
+    match *self {
+      NameSym::Unit               => 0.hash(state),
+      NameSym::Hash64(h)          => { 1.hash(state); h.hash(state) },
+      NameSym::String(ref s)      => { 2.hash(state); s.as_str().hash(state) },
+      NameSym::Usize(n)           => { 3.hash(state); n.hash(state) },
+      NameSym::Isize(n)           => { 4.hash(state); n.hash(state) },
+      NameSym::U64(n)             => { 8.hash(state); n.hash(state) },
+      NameSym::Bytes(ref b)       => { 9.hash(state); b.hash(state) },
+      NameSym::Pair(ref l, ref r) => { 5.hash(state); namesym_ptr(l).hash(state); namesym_ptr(r).hash(state) },
+      NameSym::ForkL(ref n)       => { 6.hash(state); namesym_ptr(n).hash(state) },
+      NameSym::ForkR(ref n)       => { 7.hash(state); namesym_ptr(n).hash(state) },
+    }
+  }
+}
+impl PartialEq for NameSym {
+  fn eq(&self, other:&Self) -> bool {
+    match (self, other) {
+      (&NameSym::Unit, &NameSym::Unit) => true,
+      (&NameSym::Hash64(h1), &NameSym::Hash64(h2)) => h1 == h2,
+      (&NameSym::String(ref s1), &NameSym::String(ref s2)) => s1.as_str() == s2.as_str(),
+      (&NameSym::Usize(n1), &NameSym::Usize(n2)) => n1 == n2,
+      (&NameSym::Isize(n1), &NameSym::Isize(n2)) => n1 == n2,
+      (&NameSym::U64(n1), &NameSym::U64(n2)) => n1 == n2,
+      (&NameSym::Bytes(ref b1), &NameSym::Bytes(ref b2)) => b1 == b2,
+      (&NameSym::Pair(ref l1, ref r1), &NameSym::Pair(ref l2, ref r2)) => Rc::ptr_eq(l1, l2) && Rc::ptr_eq(r1, r2),
+      (&NameSym::ForkL(ref n1), &NameSym::ForkL(ref n2)) => Rc::ptr_eq(n1, n2),
+      (&NameSym::ForkR(ref n1), &NameSym::ForkR(ref n2)) => Rc::ptr_eq(n1, n2),
+      (_, _) => false,
+    }
+  }
+}
+impl Eq for NameSym { }
+
+/// The interned name symbols of this thread, by the hash of their
+/// content.  The table holds its symbols weakly, so a symbol is freed
+/// once no name refers to it; the dead entries of a bucket are dropped
+/// when the bucket is looked up, and those of the whole table when it
+/// has doubled in size since the last sweep.
+struct NameSyms {
+  table: HashMap<u64, Vec<Weak<NameSym>>, BuildHasherDefault<FxHasher>>,
+  len: usize,
+  sweep_at: usize,
+}
+impl NameSyms {
+  fn sweep(&mut self) {
+    for bucket in self.table.values_mut() {
+      bucket.retain(|w| w.upgrade().is_some())
+    } ;
+    self.table.retain(|_, bucket| !bucket.is_empty());
+    self.len = self.table.values().map(|bucket| bucket.len()).sum();
+    self.sweep_at = if self.len < 32 { 64 } else { self.len * 2 };
+  }
+}
+thread_local!(static NAME_SYMS: RefCell<NameSyms> =
+              RefCell::new(NameSyms{ table: HashMap::default(), len: 0, sweep_at: 64 }));
+
+/// Returns the shared allocation for symbol `s`, interning `s` if it
+/// is new.
+fn intern_namesym(s:NameSym) -> Rc<NameSym> {
+  let mut hasher = FxHasher::default();
+  s.hash(&mut hasher);
+  let hash = hasher.finish();
+  NAME_SYMS.with(|syms| {
+    let mut syms = syms.borrow_mut();
+    let rc = {
+      let bucket = syms.table.entry(hash).or_insert_with(Vec::new);
+      let mut found = None;
+      bucket.retain(|w| match w.upgrade() {
+        None => false,
+        Some(rc) => { if found.is_none() && *rc == s { found = Some(rc) } ; true }
+      });
+      if let Some(rc) = found { return rc } ;
+      let rc = Rc::new(s);
+      bucket.push(Rc::downgrade(&rc));
+      rc
+    };
+    syms.len += 1;
+    if syms.len >= syms.sweep_at { syms.sweep() } ;
+    rc
+  })
+}
+
+#[cfg(test)]
+fn interned_namesym_count() -> usize {
+  NAME_SYMS.with(|syms| {
+    let mut syms = syms.borrow_mut();
+    syms.sweep();
+    syms.len
+  })
+}
+
+#[test]
+fn name_syms_are_freed () {
+  let before = interned_namesym_count();
+  {
+    let names : Vec<Name> = (0..1000usize).map(|i| name_pair(name_of_usize(i), name_of_str("x"))).collect();
+    assert!(Rc::ptr_eq(&names[7].symbol, &name_pair(name_of_usize(7), name_of_str("x")).symbol));
+    assert!(interned_namesym_count() >= before + 2000);
+  }
+  assert!(interned_namesym_count() <= before + 1);
+}
+
+/// True iff string symbol `s` may be written without quotes; see
+/// `write_namesym`.
+fn name_str_is_bare(s:&str) -> bool {
+  s.starts_with(|c:char| c.is_ascii_alphabetic()) &&
+    s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') &&
+    !s.ends_with('_') && !s.contains("__") &&
+    s != "l" && s != "r"
+}
+
+/// Writes the concise form of a symbol, which `parse_val::parse_name`
+/// parses back.  To keep this form unambiguous, strings are quoted
+/// unless they read as identifiers that are not fork suffixes (`l`
+/// and `r`) and that do not resemble path separators (`__`); each
+/// kind of number is written distinctly; and the right component of
+/// a pair is parenthesized when it is itself a pair or a fork.
 fn write_namesym<W:Write>(w:&mut W, n:&NameSym) -> Result {
   match *n {
     NameSym::Unit => write!(w, "▲"),
-    NameSym::Hash64 => write!(w, "(Hash64)"),
-    NameSym::String(ref s) => write!(w, "{}", s),
+    NameSym::Hash64(ref h) => write!(w, "#{:016x}", h),
+    NameSym::String(ref s) => {
+      if name_str_is_bare(s.as_str()) { write!(w, "{}", s.as_str()) }
+      else { write!(w, "{:?}", s.as_str()) }
+    },
     NameSym::Usize(ref n) => write!(w, "{}", n),
-    NameSym::Isize(ref n) => write!(w, "{}", n),
-    NameSym::Pair(ref l, ref r) => { write_namesym(w, l).unwrap(); write!(w, "-").unwrap(); write_namesym(w, r) },
+    NameSym::Isize(ref n) => write!(w, "{:+}", n),
+    NameSym::U64(ref n) => write!(w, "{}u64", n),
+    NameSym::Bytes(ref b) => {
+      write!(w, "0x").unwrap();
+      for byte in b.iter() { write!(w, "{:02x}", byte).unwrap() } ;
+      Ok(())
+    },
+    NameSym::Pair(ref l, ref r) => {
+      write_namesym(w, l).unwrap(); write!(w, "-").unwrap();
+      match **r {
+        NameSym::Pair(_,_) | NameSym::ForkL(_) | NameSym::ForkR(_) => {
+          write!(w, "(").unwrap(); write_namesym(w, r).unwrap(); write!(w, ")")
+        },
+        _ => write_namesym(w, r)
+      }
+    },
     NameSym::ForkL(ref s) => { write_namesym(w, s).unwrap(); write!(w, "-l") },
     NameSym::ForkR(ref s) => { write_namesym(w, s).unwrap(); write!(w, "-r") },
   }
@@ -450,25 +946,143 @@ trait GraphNode : Debug + reflect::Reflect<reflect::Node> {
   fn preds_obs<'r>   (self:&Self) -> Vec<(Rc<Loc>, Option<Rc<Box<DCGDep>>>)> ;
   fn preds_insert<'r>(self:&'r mut Self, Effect, &Rc<Loc>, Option<Rc<Box<DCGDep>>>) -> () ;
   fn preds_remove<'r>(self:&'r mut Self, &Rc<Loc>) -> () ;
+  fn preds_contains  (self:&Self, &Effect, &Rc<Loc>) -> bool ;
+  fn preds_def<'r>   (self:&Self) -> bool ;
   fn succs_def<'r>   (self:&Self) -> bool ;
-  fn succs_mut<'r>   (self:&'r mut Self) -> &'r mut Vec<Succ> ;
   fn succs<'r>       (self:&'r Self) -> &'r Vec<Succ> ;
+  fn succs_replace   (self:&mut Self, Vec<Succ>) -> Vec<Succ> ;
+  fn succ<'r>        (self:&'r Self, &Effect, &Rc<Loc>) -> Option<&'r Succ> ;
+  fn succ_mut<'r>    (self:&'r mut Self, &Effect, &Rc<Loc>) -> Option<&'r mut Succ> ;
   fn hash_seeded     (self:&Self, u64) -> u64 ;
+  fn copy            (self:&Self) -> Rc<GraphNode> ;
 }
 
 #[derive(Debug,Clone)]
 struct Frame {
   loc   : Rc<Loc>,    // The currently-executing node
-  succs : Vec<(Succ, Option<Rc<Box<DCGDep>>>)>,  // The currently-executing node's effects (viz., the nodes it demands)
+  succs : Edges<(Succ, Option<Rc<Box<DCGDep>>>)>,  // The currently-executing node's effects (viz., the nodes it demands)
+}
+
+impl Frame {
+  fn new(loc:Rc<Loc>) -> Frame { Frame{loc:loc, succs:Edges::new()} }
+}
+
+// Dependencies are compared by pointer (as are the nodes of a `DCG`).
+impl PartialEq for Frame {
+  fn eq(&self, other:&Self) -> bool {
+    self.loc == other.loc &&
+      self.succs.len() == other.succs.len() &&
+      self.succs.iter().zip(other.succs.iter()).all(|(e1, e2)| {
+        e1.0 == e2.0 && match (&e1.1, &e2.1) {
+          (&Some(ref d1), &Some(ref d2)) => Rc::ptr_eq(d1, d2),
+          (&None, &None) => true,
+          _ => false,
+        }
+      })
+  }
+}
+
+#[test]
+fn dcg_eq_within_thunk () {
+  let mut dcg = DCG::new();
+  let loc = dcg.loc_of_id(Rc::new(Path::Empty), Rc::new(ArtId::Nominal(name_of_str("t"))));
+  dcg.stack.push(Frame::new(loc.clone()));
+  assert!(dcg == dcg);
+  assert!(dcg == dcg.clone());
+  let mut other = dcg.clone();
+  other.stack.push(Frame::new(loc));
+  assert!(dcg != other);
 }
 
 impl reflect::Reflect<reflect::Frame> for Frame {
   fn reflect(&self) -> reflect::Frame {
     reflect::Frame{
       loc:self.loc.reflect(),
-      succs:self.succs.reflect(),
+      succs:self.succs.edges.reflect(),
+    }
+  }
+}
+
+/// The key of an edge in `Edges`: its effect, and the node at its
+/// other end.
+trait EdgeKey {
+  fn edge_key(&self) -> (Effect, NodeId) ;
+}
+impl EdgeKey for Succ {
+  fn edge_key(&self) -> (Effect, NodeId) { (self.effect.clone(), self.loc.node) }
+}
+impl EdgeKey for Pred {
+  fn edge_key(&self) -> (Effect, NodeId) { (self.effect.clone(), self.loc.node) }
+}
+impl EdgeKey for (Succ, Option<Rc<Box<DCGDep>>>) {
+  fn edge_key(&self) -> (Effect, NodeId) { self.0.edge_key() }
+}
+
+/// A sequence of edges, indexed by their keys (see `EdgeKey`), so
+/// that looking up, inserting and removing an edge takes constant
+/// time.  Where several edges share a key, the index gives the first.
+/// The locations given to its methods must be canonical (see
+/// `DCG::canonical_loc`).
+#[derive(Debug,Clone)]
+struct Edges<E> {
+  edges : Vec<E>,
+  index : HashMap<(Effect, NodeId), usize, BuildHasherDefault<FxHasher>>,
+}
+
+impl<E:EdgeKey> Edges<E> {
+  fn new() -> Edges<E> { Edges{edges:Vec::new(), index:HashMap::default()} }
+
+  fn from_vec(edges:Vec<E>) -> Edges<E> {
+    let mut index : HashMap<_,_,BuildHasherDefault<FxHasher>> = HashMap::default();
+    for (i, edge) in edges.iter().enumerate() {
+      index.entry(edge.edge_key()).or_insert(i);
+    }
+    Edges{edges:edges, index:index}
+  }
+
+  fn into_vec(self) -> Vec<E> { self.edges }
+
+  fn len(&self) -> usize { self.edges.len() }
+
+  fn iter(&self) -> ::std::slice::Iter<E> { self.edges.iter() }
+
+  fn push(&mut self, edge:E) {
+    let i = self.edges.len();
+    self.index.entry(edge.edge_key()).or_insert(i);
+    self.edges.push(edge)
+  }
+
+  fn get(&self, eff:&Effect, loc:&Rc<Loc>) -> Option<&E> {
+    match self.index.get(&(eff.clone(), loc.node)) {
+      Some(&i) => Some(&self.edges[i]),
+      None => None,
     }
   }
+
+  fn get_mut(&mut self, eff:&Effect, loc:&Rc<Loc>) -> Option<&mut E> {
+    match self.index.get(&(eff.clone(), loc.node)) {
+      Some(&i) => Some(&mut self.edges[i]),
+      None => None,
+    }
+  }
+
+  /// Removes the edge with the given key, moving the last edge into
+  /// its place.  For sequences whose keys are unique.
+  fn swap_remove(&mut self, eff:&Effect, loc:&Rc<Loc>) -> Option<E> {
+    let i = match self.index.remove(&(eff.clone(), loc.node)) {
+      Some(i) => i,
+      None => return None,
+    } ;
+    let edge = self.edges.swap_remove(i);
+    if i < self.edges.len() {
+      self.index.insert(self.edges[i].edge_key(), i);
+    } ;
+    Some(edge)
+  }
+}
+
+impl<E:Hash> Hash for Edges<E> {
+  fn hash<H:Hasher>(&self, h: &mut H) { self.edges.hash(h) }
 }
 
 #[derive(Debug,Clone)]
@@ -479,6 +1093,13 @@ struct Succ {
   dep    : Rc<Box<DCGDep>>, // Abstracted dependency information (e.g., for Observe Effect, the prior observed value)
 }
 
+impl PartialEq for Succ {
+  fn eq(&self, other:&Self) -> bool {
+    self.dirty == other.dirty && self.loc == other.loc &&
+      self.effect == other.effect && Rc::ptr_eq(&self.dep, &other.dep)
+  }
+}
+
 #[derive(Debug,Clone)]
 struct Pred {
   loc    : Rc<Loc>, // Source of the effect, aka, the predecessor, by this edge
@@ -499,7 +1120,7 @@ impl reflect::Reflect<reflect::Succ> for Succ {
       dirty:self.dirty,
       loc:self.loc.reflect(),
       effect:self.effect.reflect(),
-      value:reflect::Val::ValTODO,
+      value:(**self.dep).reflect(),
       is_dup:false, // XXX -- Actually: Not checked here.
     }
   }
@@ -540,11 +1161,37 @@ struct DCGRes {
     // dirtying or reevaluation, respectively.
     changed : bool,
 }
+
+/// The first step of cleaning a dependency (see `clean_dirty_succs`).
+enum CleanStep {
+  /// The dependency is clean, and its value has (or has not) changed.
+  Done(DCGRes),
+  /// The dependency is on a thunk with a cached result, and these
+  /// successors of the thunk must be cleaned (in order, until one has
+  /// changed) before `DCGDep::clean_end` finishes cleaning it.
+  Succs(Vec<Succ>),
+}
+
 // DCGDep abstracts over the value produced by a dependency, as
-// well as mechanisms to update and/or re-produce it.
-trait DCGDep : Debug {
+// well as mechanisms to update and/or re-produce it.  Its reflection
+// is this value (e.g., the value observed by a force, or written by
+// an allocation).
+trait DCGDep : Debug + reflect::Reflect<reflect::Val> {
   fn dirty (self:&Self, g:&mut DCG,      loc:&Rc<Loc>) -> DCGRes ;
-  fn clean (self:&Self, g:&RefCell<DCG>, loc:&Rc<Loc>) -> DCGRes ;
+  /// Begins cleaning the dependency on `loc`; see `CleanStep`.
+  fn clean_begin (self:&Self, g:&RefCell<DCG>, loc:&Rc<Loc>) -> CleanStep ;
+  /// Finishes cleaning the dependency on the thunk at `loc`, after
+  /// `clean_begin` gives `CleanStep::Succs`, and the thunk's
+  /// successors are clean; first re-evaluates the thunk if `reeval`
+  /// holds (when one of its successors has changed).
+  fn clean_end (self:&Self, _g:&RefCell<DCG>, _loc:&Rc<Loc>, _reeval:bool) -> DCGRes {
+    unreachable!()
+  }
+  /// The result type of the node that the dependency targets, when the dependency determines it.
+  fn res_typeid (self:&Self) -> Option<TypeId> ;
+  /// True iff the dependency shares (by `Rc`) the value of the node
+  /// that it targets; see `DCG::update`.
+  fn shares_res (self:&Self) -> bool { false }
 }
 
 impl Hash for Succ {
@@ -564,7 +1211,7 @@ impl Hash for Pred {
 
 
 // Structureful (Non-opaque) nodes:
-#[allow(dead_code)] // Pure case: not introduced currently.
+#[allow(dead_code)] // Unused case: not introduced currently.
 #[derive(Debug,Hash)]
 enum Node<Res> {
   Comp(CompNode<Res>),
@@ -574,16 +1221,16 @@ enum Node<Res> {
 }
 impl<X:Debug> reflect::Reflect<reflect::Node> for Node<X> {
   fn reflect(&self) -> reflect::Node {
-    use parse_val::parse_val;
+    use self::reflect::reflect_val;
     match *self {
       Node::Comp(ref n) => {
         reflect::Node::Comp(
           reflect::CompNode{
-            preds:n.preds.reflect(),
-            succs:n.succs.reflect(),
+            preds:n.preds.edges.reflect(),
+            succs:n.succs.edges.reflect(),
             prog_pt:n.producer.prog_pt().clone(),
             value:match n.res { 
-              Some(ref v) => Some( parse_val(v) ),
+              Some(ref v) => Some( reflect_val(v) ),
               None => None
             }
           })
@@ -591,14 +1238,14 @@ impl<X:Debug> reflect::Reflect<reflect::Node> for Node<X> {
       Node::Pure(ref n) => {
         reflect::Node::Pure(
           reflect::PureNode {
-            value:parse_val( &n.val ),
+            value:reflect_val( &n.val ),
           })
       },
       Node::Mut(ref n) => {
         reflect::Node::Ref(
           reflect::RefNode {
-            preds:n.preds.reflect(),
-            value:parse_val( &n.val ),
+            preds:n.preds.edges.reflect(),
+            value:reflect_val( &n.val ),
           })        
       },
       Node::Unused => panic!(""),
@@ -606,11 +1253,11 @@ impl<X:Debug> reflect::Reflect<reflect::Node> for Node<X> {
   }
 }
 
-// PureNode<T> for pure hash-consing of T's.
+// PureNode<T> for pure hash-consing of T's (see `intern`).
 // Location in table never changes value.
 #[derive(Debug,Hash)]
 struct PureNode<T> {
-  val : T,
+  val : Rc<T>,
 }
 
 // MutNode<T> for mutable content of type T.
@@ -619,8 +1266,8 @@ struct PureNode<T> {
 // They may indirectly mutate these nodes by performing nominal allocation; mutation is limited to "one-shot" changes.
 #[derive(Debug,Hash)]
 struct MutNode<T> {
-  preds : Vec<Pred>,
-  val   : T,
+  preds : Edges<Pred>, // with unique keys
+  val   : Rc<T>,
 }
 
 // CompNode<Res> for a suspended computation whose resulting value of
@@ -628,11 +1275,14 @@ struct MutNode<T> {
 // (1) producer may change, which may affect the result and (2) the
 // values produced by the successors may change, indirectly
 // influencing how the producer produces its resulting value.
+//
+// Node values and results are shared (by `Rc`) with the `ForceDep`s
+// that observe them, so that observing a value need not copy it.
 struct CompNode<Res> {
-  preds    : Vec<Pred>,
-  succs    : Vec<Succ>,
+  preds    : Edges<Pred>, // with unique keys
+  succs    : Edges<Succ>,
   producer : Box<Producer<Res>>, // Producer can be App<Arg,Res>, where type Arg is hidden.
-  res      : Option<Res>,
+  res      : Option<Rc<Res>>,
 }
 
 impl reflect::Reflect<Vec<reflect::Pred>> for Vec<Pred> {
@@ -743,14 +1393,14 @@ impl<Arg:Clone+PartialEq+Eq+Debug,Spurious,Res>
 
 // ----------- Location resolution:
 
-fn lookup_abs<'r>(st:&'r mut DCG, loc:&Rc<Loc>) -> &'r mut Box<GraphNode> {
-  match st.table.get_mut( loc ) {
+fn lookup_abs<'r>(st:&'r mut DCG, loc:&Rc<Loc>) -> &'r mut GraphNode {
+  match st.node_mut( loc ) {
     None => panic!("dangling pointer: {:?}", loc),
-    Some(node) => node.be_node() // This is a weird workaround; TODO-Later: Investigate.
+    Some(node) => node
   }
 }
 
-fn assert_graphnode_res_type<Res:'static> (loc:&Loc, node:&Box<GraphNode>) {
+fn assert_graphnode_res_type<Res:'static> (loc:&Loc, node:&GraphNode) {
     let res_typeid = TypeId::of::<Res>();
     let node_res_typeid = node.res_typeid();
     if node_res_typeid != res_typeid {
@@ -764,18 +1414,28 @@ Adapton engine: Detected a dynamic type error, possibly due to an ambiguous name
     }
 }
 
-// This function uses 'unsafe' to transmute pointer types. Unintended
+// This function uses 'unsafe' to cast pointer types. Unintended
 // double-uses of names and hashes will cause dynamic type errors, via
 // assert_graphnode_res_type.
-fn res_node_of_loc<'r,Res:'static> (st:&'r mut DCG, loc:&Rc<Loc>) -> &'r mut Box<Node<Res>> {
+fn res_node_of_loc<'r,Res:'static> (st:&'r mut DCG, loc:&Rc<Loc>) -> &'r mut Node<Res> {
   let abs_node = lookup_abs(st, loc) ;
   assert_graphnode_res_type::<Res>(&*loc, abs_node);
-  unsafe { transmute::<_,_>(abs_node) }
+  unsafe { &mut *(abs_node as *mut GraphNode as *mut Node<Res>) }
+}
+
+// Like `res_node_of_loc`, but borrows the DCG immutably.
+fn res_node_of_loc_ref<'r,Res:'static> (st:&'r DCG, loc:&Rc<Loc>) -> &'r Node<Res> {
+  let abs_node = match st.node( loc ) {
+    None => panic!("dangling pointer: {:?}", loc),
+    Some(node) => node
+  } ;
+  assert_graphnode_res_type::<Res>(&*loc, abs_node);
+  unsafe { &*(abs_node as *const GraphNode as *const Node<Res>) }
 }
 
 // ---------- Node implementation:
 
-impl <Res:'static+Debug+Hash> GraphNode for Node<Res> {
+impl <Res:'static+Debug+Hash+Clone> GraphNode for Node<Res> {
 
   fn res_typeid(self:&Self) -> TypeId {
       return TypeId::of::<Res>()
@@ -808,53 +1468,81 @@ impl <Res:'static+Debug+Hash> GraphNode for Node<Res> {
           _ => unreachable!(),
     }}
   fn preds_insert (self:&mut Self, eff:Effect, loc:&Rc<Loc>, dep:Option<Rc<Box<DCGDep>>>) -> () {
-    match *self { Node::Mut(ref mut nd) => nd.preds.push (Pred{effect:eff,loc:loc.clone(),dep:dep.clone()}),
-                  Node::Comp(ref mut nd) => nd.preds.push (Pred{effect:eff,loc:loc.clone(),dep:dep.clone()}),
-                  Node::Pure(_) => unreachable!(),
-                  _ => unreachable!(),
-    }}
+    let preds = match *self { Node::Mut(ref mut nd) => &mut nd.preds,
+                              Node::Comp(ref mut nd) => &mut nd.preds,
+                              Node::Pure(_) => unreachable!(),
+                              _ => unreachable!(),
+    } ;
+    // Keep keys unique: a second edge with the same key (e.g., a
+    // second allocation of `loc`) merges with the first; where their
+    // dependencies differ, the merged edge observes `loc` fully.
+    if let Some(pred) = preds.get_mut(&eff, loc) {
+      let same = match (&pred.dep, &dep) {
+        (&Some(ref d1), &Some(ref d2)) => Rc::ptr_eq(d1, d2),
+        (&None, &None) => true,
+        _ => false,
+      } ;
+      if !same { pred.dep = None } ;
+      return
+    } ;
+    preds.push(Pred{effect:eff,loc:loc.clone(),dep:dep})
+  }
   fn preds_remove (self:&mut Self, loc:&Rc<Loc>) -> () {
-    match *self { Node::Mut(ref mut nd) => nd.preds.retain (|pred|{ &pred.loc != loc}),
-                  Node::Comp(ref mut nd) => nd.preds.retain (|pred|{ &pred.loc != loc}),
-                  Node::Pure(_) => unreachable!(),
-                  _ => unreachable!(),
+    let preds = match *self { Node::Mut(ref mut nd) => &mut nd.preds,
+                              Node::Comp(ref mut nd) => &mut nd.preds,
+                              Node::Pure(_) => unreachable!(),
+                              _ => unreachable!(),
+    } ;
+    preds.swap_remove(&Effect::Observe, loc);
+    preds.swap_remove(&Effect::Allocate, loc);
+  }
+  fn preds_contains (self:&Self, eff:&Effect, loc:&Rc<Loc>) -> bool {
+    match *self { Node::Mut(ref nd) => nd.preds.get(eff, loc).is_some(),
+                  Node::Comp(ref nd) => nd.preds.get(eff, loc).is_some(),
+                  _ => false,
+    }}
+  fn preds_def(self:&Self) -> bool {
+    match *self { Node::Mut(_) | Node::Comp(_) => true, _ => false
     }}
   fn succs_def(self:&Self) -> bool {
     match *self { Node::Comp(_) => true, _ => false
     }}
-  fn succs_mut<'r>(self:&'r mut Self) -> &'r mut Vec<Succ> {
-    match *self { Node::Comp(ref mut n) => &mut n.succs,
+  fn succs<'r>(self:&'r Self) -> &'r Vec<Succ> {
+    match *self { Node::Comp(ref n) => &n.succs.edges,
                   _ => panic!("undefined"),
     }
   }
-  fn succs<'r>(self:&'r Self) -> &'r Vec<Succ> {
-    match *self { Node::Comp(ref n) => &n.succs,
+  fn succs_replace(self:&mut Self, succs:Vec<Succ>) -> Vec<Succ> {
+    match *self { Node::Comp(ref mut n) => replace(&mut n.succs, Edges::from_vec(succs)).into_vec(),
                   _ => panic!("undefined"),
     }
   }
-  fn hash_seeded(self:&Self, seed:u64) -> u64 {
-    let mut hasher = DefaultHasher::new();
-    seed.hash(&mut hasher);
-    self.hash(&mut hasher);
-    hasher.finish()
+  fn succ<'r>(self:&'r Self, eff:&Effect, loc:&Rc<Loc>) -> Option<&'r Succ> {
+    match *self { Node::Comp(ref n) => n.succs.get(eff, loc),
+                  _ => None,
+    }
   }
-}
-
-trait ShapeShifter {
-  fn be_node<'r> (self:&'r mut Self) -> &'r mut Box<GraphNode> ;
-}
-
-impl <Res> ShapeShifter for Box<Node<Res>> {
-  fn be_node<'r>(self:&'r mut Self) -> &'r mut Box<GraphNode> {
-    // TODO-Later: Why is this transmute needed here ??
-    unsafe { transmute::<_,_>(self) }
+  fn succ_mut<'r>(self:&'r mut Self, eff:&Effect, loc:&Rc<Loc>) -> Option<&'r mut Succ> {
+    match *self { Node::Comp(ref mut n) => n.succs.get_mut(eff, loc),
+                  _ => None,
+    }
   }
-}
-
-impl ShapeShifter for Box<GraphNode> {
-  fn be_node<'r>(self:&'r mut Self) -> &'r mut Box<GraphNode> {
-    // TODO-Later: Why is this transmute needed here ??
-    unsafe { transmute::<_,_>(self) }
+  fn hash_seeded(self:&Self, seed:u64) -> u64 {
+    my_hash(&(seed, self))
+  }
+
+  fn copy(self:&Self) -> Rc<GraphNode> {
+    Rc::new(match *self {
+      Node::Comp(ref nd) => Node::Comp(CompNode{
+        preds:nd.preds.clone(),
+        succs:nd.succs.clone(),
+        producer:nd.producer.copy(),
+        res:nd.res.clone(),
+      }),
+      Node::Pure(ref nd) => Node::Pure(PureNode{val:nd.val.clone()}),
+      Node::Mut(ref nd)  => Node::Mut(MutNode{preds:nd.preds.clone(), val:nd.val.clone()}),
+      Node::Unused       => Node::Unused,
+    })
   }
 }
 
@@ -882,17 +1570,16 @@ impl<Res:Hash> Hash for CompNode<Res> {
 /// Re-evaluation: `loc_produce` performs the computation at `loc`,
 /// and produces a result of type `Res`.  Error if `loc` is not a
 /// `Node::Comp`.
-fn loc_produce<Res:'static+Debug+PartialEq+Eq+Clone+Hash>(g:&RefCell<DCG>, loc:&Rc<Loc>) -> Res
+fn loc_produce<Res:'static+Debug+PartialEq+Eq+Clone+Hash>(g:&RefCell<DCG>, loc:&Rc<Loc>) -> Rc<Res>
 {
   let (producer, prev_path) = {
     let st : &mut DCG = &mut *g.borrow_mut() ;
     let succs : Vec<Succ> = {
-      let succs : Vec<Succ> = Vec::new();
       let node : &mut Node<Res> = res_node_of_loc( st, loc ) ;
-      replace(node.succs_mut(), succs)
+      node.succs_replace(Vec::new())
     } ;
     revoke_succs( st, loc, &succs );
-    st.stack.push ( Frame{loc:loc.clone(), succs:Vec::new(), } );
+    st.stack.push ( Frame::new(loc.clone()) );
     //st.cnt.stack = if st.cnt.stack > st.stack.len() { st.cnt.stack } else { st.stack.len() } ;
     let prev_path = st.path.clone () ;
     st.path = loc.path.clone() ;
@@ -913,7 +1600,7 @@ fn loc_produce<Res:'static+Debug+PartialEq+Eq+Clone+Hash>(g:&RefCell<DCG>, loc:&
   /// engine library.  That's why we end the mutable borrow of `g`
   /// above, before making this call.  We re-borrow `g` below, when
   /// the call is complete.
-  let res = producer.produce() ;
+  let res = Rc::new(producer.produce()) ;
   // - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - -
   let st = &mut * g.borrow_mut() ;
   st.path = prev_path ;
@@ -922,7 +1609,7 @@ fn loc_produce<Res:'static+Debug+PartialEq+Eq+Clone+Hash>(g:&RefCell<DCG>, loc:&
     Some(frame) => frame
   } ;
   assert!( &frame.loc == loc );
-  for succ in &frame.succs {
+  for succ in frame.succs.iter() {
     if succ.0.dirty {
       // This case witnesses an illegal use of nominal side effects
       panic!("invariants broken: newly-built DCG edge should be clean, but is dirty.")
@@ -934,8 +1621,8 @@ fn loc_produce<Res:'static+Debug+PartialEq+Eq+Clone+Hash>(g:&RefCell<DCG>, loc:&
     let node : &mut Node<Res> = res_node_of_loc( st, loc ) ;
     match *node {
       Node::Comp(ref mut node) => {
-        replace(&mut node.succs, frame.succs.into_iter().map(|(succ,_)|succ).collect() ) ;
-        replace(&mut node.res, Some(res.clone()))
+        node.succs = Edges::from_vec(frame.succs.into_vec().into_iter().map(|(succ,_)|succ).collect()) ;
+        node.res = Some(res.clone())
       },
       _ => panic!("internal error"),
     }
@@ -943,87 +1630,389 @@ fn loc_produce<Res:'static+Debug+PartialEq+Eq+Clone+Hash>(g:&RefCell<DCG>, loc:&
   res
 }
 
-fn clean_comp<Res:'static+Sized+Debug+PartialEq+Clone+Eq+Hash>
-  (g:&RefCell<DCG>,
-   this_dep:&ForceDep<Res>,
-   loc:&Rc<Loc>, cache:Res, succs:Vec<Succ>) -> DCGRes
+/// Verify mode (see `Flags::verify_cache_hits`): Reruns the producer
+/// at `loc` in a throwaway fork of the DCG (see `DCG::clone`), and
+/// panics if its result differs from `cached`.  The rerun's edges and
+/// allocations stay in the fork, leaving the DCG untouched.
+/// Verification is off in the fork, so each cache hit costs one extra
+/// run of its producer (and not of its transitive callees).
+fn verify_cache_hit<Res:'static+Debug+PartialEq+Eq+Clone+Hash>(g:&RefCell<DCG>, loc:&Rc<Loc>, cached:&Res)
 {
-  for succ in succs.iter() {
-    let dirty = {
-      let mut st = &mut *g.borrow_mut();
-      get_succ_mut(st, loc, succ.effect.clone(), &succ.loc).dirty
+  let (producer, _restore) = {
+    let st : &mut DCG = &mut *g.borrow_mut() ;
+    let mut fork = st.clone() ;
+    fork.flags.verify_cache_hits = false ;
+    fork.stack.push ( Frame::new(loc.clone()) );
+    fork.path = loc.path.clone() ;
+    let producer : Box<Producer<Res>> = {
+      let node : &mut Node<Res> = res_node_of_loc( &mut fork, loc ) ;
+      match *node {
+        Node::Comp(ref nd) => nd.producer.copy(),
+        _ => panic!("internal error"),
+      }
     } ;
-    if dirty {
-      dcg_effect_begin!(reflect::trace::Effect::CleanRec, Some(loc), succ);
-      let succ_dep = & succ.dep ;
-      let res = succ_dep.clean(g, &succ.loc) ;
-      if res.changed {        
-        dcg_effect_begin!(reflect::trace::Effect::CleanEval, Some(loc), succ);
-        let result : Res = loc_produce( g, loc ) ;
-        dcg_effect_end!();
-        let changed = result != this_dep.res ;
-        dcg_effect_end!();
-        return DCGRes{changed:changed}
+    (producer, RestoreDCG(g, Some(replace(st, fork))))
+  } ;
+  let res = producer.produce() ;
+  if &res != cached {
+    panic!("verify: cache hit at {:?} (prog_pt {:?}) has cached result {:?}, but rerunning its producer gives {:?}",
+           loc, producer.prog_pt(), cached, res)
+  }
+}
+
+/// Puts back the DCG that it holds when dropped, discarding the DCG
+/// in its place (e.g., the fork of `verify_cache_hit`, even when the
+/// rerun panics).
+struct RestoreDCG<'r>(&'r RefCell<DCG>, Option<DCG>);
+
+impl<'r> Drop for RestoreDCG<'r> {
+  fn drop(&mut self) {
+    if let (Ok(mut st), Some(dcg)) = (self.0.try_borrow_mut(), self.1.take()) { *st = dcg }
+  }
+}
+
+/// Reflects the current value at `loc`, for tracing.
+fn reflect_res<T:'static+Debug> (g:&RefCell<DCG>, loc:&Rc<Loc>) -> reflect::Val {
+  let st : &DCG = &*g.borrow();
+  let node : &Node<T> = res_node_of_loc_ref(st, loc) ;
+  match *node {
+    Node::Comp(ref nd) => match nd.res { Some(ref res) => reflect::reflect_val(res), None => reflect::Val::ValTODO },
+    Node::Pure(ref nd) => reflect::reflect_val(&nd.val),
+    Node::Mut(ref nd)  => reflect::reflect_val(&nd.val),
+    _ => panic!("undefined")
+  }
+}
+
+/// Applies `f` to the current value at `loc`, borrowing it in place.
+/// `f` must not mutate the engine, which remains borrowed.
+fn with_loc_res<T:'static,R,F:FnOnce(&Rc<T>) -> R> (g:&RefCell<DCG>, loc:&Rc<Loc>, f:F) -> R {
+  let st : &DCG = &*g.borrow();
+  let node : &Node<T> = res_node_of_loc_ref(st, loc) ;
+  match *node {
+    Node::Comp(ref nd) => match nd.res {
+      None => unreachable!(),
+      Some(ref res) =>
+        // Testing: Reached by `pure_caching` tests
+        f(res)
+    },
+    Node::Pure(ref nd) => f(&nd.val),
+    Node::Mut(ref nd)  => f(&nd.val),
+    _ => panic!("undefined")
+  }
+}
+
+/// The current value at `loc`, shared with its node (not copied).
+fn loc_res<T:'static> (g:&RefCell<DCG>, loc:&Rc<Loc>) -> Rc<T> {
+  with_loc_res(g, loc, |res:&Rc<T>| res.clone())
+}
+
+/// Forces the thunk or cell at `loc`, bringing it up to date, but
+/// records no edge from the current frame, and copies no values;
+/// returns whether an edge from the current frame to `loc` would be
+/// duplicate, or is unneeded because `loc` is pure.
+fn force_loc_clean<T:'static+Eq+Debug+Clone+Hash> (g:&RefCell<DCG>, loc:&Rc<Loc>) -> (bool, bool)
+{
+  let (is_comp, is_dup, is_pure, has_res, succs) : (bool, bool, bool, bool, Vec<Succ>) = {
+    let st : &mut DCG = &mut *g.borrow_mut();
+    let is_pure_opt : bool = st.flags.use_purity_optimization ;
+    let is_dup : bool = match st.stack.last() { None => false, Some(frame) => {
+        frame.succs.get(&Effect::Observe, loc).is_some()
+    }};
+    let node : &mut Node<T> = res_node_of_loc(st, &loc) ;
+    match *node {
+      Node::Pure(_) => (false, is_dup, true, true, vec![]),
+      Node::Mut(_)  => (false, is_dup, false, true, vec![]),
+      Node::Comp(ref mut nd) => {
+        let is_pure = match *loc.id {
+          ArtId::Structural(_) => nd.succs.len() == 0 && is_pure_opt,
+          ArtId::Nominal(_)    => false } ;
+        (true, is_dup, is_pure, nd.res.is_some(), nd.succs.edges.clone()) },
+      _ => panic!("undefined")
+    }
+  } ;
+  if !has_res {
+    assert!(is_comp);
+    dcg_effect_begin!(
+      reflect::trace::Effect::Force(reflect::trace::ForceCase::CompCacheMiss),
+      current_loc!(*g.borrow()),
+      reflect::Succ{
+        loc:loc.reflect(),
+        // No value yet: The effects in this extent produce it.
+        value:reflect::Val::ValTODO,
+        effect:reflect::Effect::Force,
+        dirty:false,
+        is_dup:is_dup,
       }
-      else {
-        let mut st : &mut DCG = &mut *g.borrow_mut();
-        //st.cnt.clean += 1 ;
-        get_succ_mut(st, loc, succ.effect.clone(), &succ.loc).dirty = false ;
-        dcg_effect!(reflect::trace::Effect::CleanEdge, Some(loc), succ);
+    );
+    assert_eq!(is_dup, false);
+    let _ : Rc<T> = loc_produce(g, &loc);
+    dcg_effect_end!();
+  }
+  else if is_comp {
+    dcg_effect_begin!(
+      reflect::trace::Effect::Force(reflect::trace::ForceCase::CompCacheHit),
+      current_loc!(*g.borrow()),
+      reflect::Succ{
+        loc:loc.reflect(),
+        // The cached value, before the cleaning in this extent.
+        value:reflect_res::<T>(g, &loc),
+        effect:reflect::Effect::Force,
+        dirty:false,
+        is_dup:is_dup,
       }
-      dcg_effect_end!();
+    );
+    let _ : Option<Rc<T>> = clean_succs(g, &loc, succs) ;
+    dcg_effect_end!();
+    if g.borrow().flags.verify_cache_hits {
+      let res : Rc<T> = loc_res(g, &loc) ;
+      verify_cache_hit(g, &loc, &*res)
     }
+  }
+  else {
+    dcg_effect!(
+      reflect::trace::Effect::Force(reflect::trace::ForceCase::RefGet),
+      current_loc!(*g.borrow()),
+      reflect::Succ{
+        loc:loc.reflect(),
+        value:reflect_res::<T>(g, &loc),
+        effect:reflect::Effect::Force,
+        dirty:false,
+        is_dup:is_dup,
+      });
   } ;
-  let changed = this_dep.res != cache ;
-  DCGRes{changed:changed}
+  (is_dup, is_pure)
+}
+
+/// Like `force_loc_clean`, but also returns the result (shared with
+/// the node, not copied).
+fn force_loc<T:'static+Eq+Debug+Clone+Hash> (g:&RefCell<DCG>, loc:&Rc<Loc>) -> (Rc<T>, bool, bool)
+{
+  let (is_dup, is_pure) = force_loc_clean::<T>(g, loc) ;
+  (loc_res(g, loc), is_dup, is_pure)
+}
+
+/// Cleans the dirty successors of the thunk at `loc`, in order,
+/// until one has changed; then, re-evaluates the thunk, and returns
+/// its new result.  Returns `None` if no successor has changed.
+fn clean_succs<Res:'static+Sized+Debug+PartialEq+Clone+Eq+Hash>
+  (g:&RefCell<DCG>, loc:&Rc<Loc>, succs:Vec<Succ>) -> Option<Rc<Res>>
+{
+  match clean_dirty_succs(g, loc, succs) {
+    None => None,
+    Some(succ) => {
+      dcg_effect_begin!(reflect::trace::Effect::CleanEval, Some(loc), succ);
+      let result : Rc<Res> = loc_produce( g, loc ) ;
+      dcg_effect_end!();
+      dcg_effect_end!();
+      Some(result)
+    }
+  }
+}
+
+/// A thunk whose successors `clean_dirty_succs` is cleaning; those
+/// before index `next` are clean.
+struct CleanFrame {
+  loc   : Rc<Loc>,
+  succs : Vec<Succ>,
+  next  : usize,
+}
+
+/// Cleans the dirty successors of the thunk at `loc`, in order,
+/// until one has changed, and returns that successor; the caller
+/// re-evaluates the thunk, and then ends the (`CleanRec`) extent of
+/// this successor.  Returns `None` if no successor has changed.
+///
+/// Cleaning a successor may require cleaning its own successors, and
+/// so on, along arbitrarily long chains of dirty edges.  Rather than
+/// recurse along these chains, this function keeps an explicit stack
+/// of the thunks being cleaned, and finishes cleaning each thunk
+/// (via `DCGDep::clean_end`) once its successors are clean, bottom-up.
+fn clean_dirty_succs(g:&RefCell<DCG>, loc:&Rc<Loc>, succs:Vec<Succ>) -> Option<Succ>
+{
+  let mut stack = vec![ CleanFrame{ loc:loc.clone(), succs:succs, next:0 } ];
+  // The result of cleaning the next successor of the top frame, once known.
+  let mut succ_res : Option<DCGRes> = None ;
+  loop {
+    match succ_res.take() {
+      None => (),
+      Some(DCGRes{changed:true}) => {
+        let frame = stack.pop().unwrap() ;
+        let succ = frame.succs[frame.next].clone() ;
+        match stack.last() {
+          None => return Some(succ),
+          Some(parent) => {
+            // The frame's thunk is the parent's next successor.
+            dcg_effect_begin!(reflect::trace::Effect::CleanEval, Some(&frame.loc), succ);
+            let via = &parent.succs[parent.next] ;
+            succ_res = Some(via.dep.clean_end(g, &frame.loc, true)) ;
+            dcg_effect_end!();
+            dcg_effect_end!();
+            continue
+          }
+        }
+      },
+      Some(DCGRes{changed:false}) => {
+        let top = stack.last_mut().unwrap() ;
+        {
+          let succ = &top.succs[top.next] ;
+          let st : &mut DCG = &mut *g.borrow_mut();
+          get_succ_mut(st, &top.loc, succ.effect.clone(), &succ.loc).dirty = false ;
+          dcg_effect!(reflect::trace::Effect::CleanEdge, Some(&top.loc), succ);
+          dcg_effect_end!();
+        }
+        top.next += 1 ;
+      },
+    } ;
+    let step = {
+      let top = stack.last_mut().unwrap() ;
+      while top.next < top.succs.len() && {
+        let succ = &top.succs[top.next] ;
+        let st : &mut DCG = &mut *g.borrow_mut();
+        !get_succ_mut(st, &top.loc, succ.effect.clone(), &succ.loc).dirty
+      } { top.next += 1 } ;
+      if top.next == top.succs.len() { None } else {
+        let succ = &top.succs[top.next] ;
+        dcg_effect_begin!(reflect::trace::Effect::CleanRec, Some(&top.loc), succ);
+        Some((succ.loc.clone(), succ.dep.clean_begin(g, &succ.loc)))
+      }
+    } ;
+    match step {
+      Some((_, CleanStep::Done(res))) => succ_res = Some(res),
+      Some((succ_loc, CleanStep::Succs(succs))) =>
+        stack.push(CleanFrame{ loc:succ_loc, succs:succs, next:0 }),
+      None => {
+        // No successor of the frame's thunk has changed.
+        let frame = stack.pop().unwrap() ;
+        match stack.last() {
+          None => return None,
+          Some(parent) => {
+            let via = &parent.succs[parent.next] ;
+            succ_res = Some(via.dep.clean_end(g, &frame.loc, false))
+          }
+        }
+      }
+    }
+  }
 }
 
 #[derive(Debug)]
 struct AllocStructuralThunk;
+impl reflect::Reflect<reflect::Val> for AllocStructuralThunk {
+  // The allocation's argument is not stored here; only its hash is (in the `Loc`).
+  fn reflect(&self) -> reflect::Val { reflect::Val::ValTODO }
+}
 impl DCGDep for AllocStructuralThunk {
   fn dirty (self:&Self, _g:&mut DCG,      _loc:&Rc<Loc>) -> DCGRes { DCGRes{changed:true} }
-  fn clean (self:&Self, _g:&RefCell<DCG>, _loc:&Rc<Loc>) -> DCGRes { DCGRes{changed:false} }
+  fn clean_begin (self:&Self, _g:&RefCell<DCG>, _loc:&Rc<Loc>) -> CleanStep { CleanStep::Done(DCGRes{changed:false}) }
+  fn res_typeid (self:&Self) -> Option<TypeId> { None }
 }
 
 #[derive(Debug)]
 struct AllocNominalThunk<T> { val:T }
+impl<T:Debug> reflect::Reflect<reflect::Val> for AllocNominalThunk<T> {
+  fn reflect(&self) -> reflect::Val { reflect::reflect_val(&self.val) }
+}
 impl<T:Debug> DCGDep for AllocNominalThunk<T> {
   fn dirty (self:&Self, _g:&mut DCG,      _loc:&Rc<Loc>) -> DCGRes { DCGRes{changed:true} }
-  fn clean (self:&Self, _g:&RefCell<DCG>, _loc:&Rc<Loc>) -> DCGRes { DCGRes{changed:true} } // TODO-Later: Make this a little better.
+  fn clean_begin (self:&Self, _g:&RefCell<DCG>, _loc:&Rc<Loc>) -> CleanStep { CleanStep::Done(DCGRes{changed:true}) } // TODO-Later: Make this a little better.
+  // The stored value is the thunk's argument, not its result.
+  fn res_typeid (self:&Self) -> Option<TypeId> { None }
 }
 
 #[derive(Debug)]
 struct AllocCell<T> { val:T }
-impl<T:Debug> DCGDep for AllocCell<T> {
+impl<T:Debug> reflect::Reflect<reflect::Val> for AllocCell<T> {
+  fn reflect(&self) -> reflect::Val { reflect::reflect_val(&self.val) }
+}
+impl<T:'static+Debug> DCGDep for AllocCell<T> {
   fn dirty (self:&Self, _g:&mut DCG,      _loc:&Rc<Loc>) -> DCGRes { DCGRes{changed:true} }
-  fn clean (self:&Self, _g:&RefCell<DCG>, _loc:&Rc<Loc>) -> DCGRes { DCGRes{changed:true} } // TODO-Later: Make this a little better.
+  fn clean_begin (self:&Self, _g:&RefCell<DCG>, _loc:&Rc<Loc>) -> CleanStep { CleanStep::Done(DCGRes{changed:true}) } // TODO-Later: Make this a little better.
+  fn res_typeid (self:&Self) -> Option<TypeId> { Some(TypeId::of::<T>()) }
+}
+
+#[derive(Debug)]
+struct UpdatedCell;
+impl reflect::Reflect<reflect::Val> for UpdatedCell {
+  // The observed value is not stored here; see `DCG::update`.
+  fn reflect(&self) -> reflect::Val { reflect::Val::ValTODO }
+}
+/// The dependency of an observer on a cell that `update` has changed
+/// in place: rather than keep a copy of the observed value to compare
+/// against, it reports a change.
+impl DCGDep for UpdatedCell {
+  fn dirty (self:&Self, _g:&mut DCG,      _loc:&Rc<Loc>) -> DCGRes { DCGRes{changed:true} }
+  fn clean_begin (self:&Self, _g:&RefCell<DCG>, _loc:&Rc<Loc>) -> CleanStep { CleanStep::Done(DCGRes{changed:true}) }
+  fn res_typeid (self:&Self) -> Option<TypeId> { None }
 }
 
 /// The structure implements DCGDep, caching a value of type `T` to
 /// compare against future values.
 #[derive(Debug)]
-struct ForceDep<T:Debug> { res:T }
+struct ForceDep<T:Debug> { res:Rc<T> }
+impl<T:Debug> reflect::Reflect<reflect::Val> for ForceDep<T> {
+  fn reflect(&self) -> reflect::Val { reflect::reflect_val(&self.res) }
+}
 
 /// The structure implements DCGDep, caching a value of type `T` to
 /// compare against future values.
 struct ForceMapDep<T,S,F:Fn(&Art<T>, T)->S> { raw:PhantomData<T>, mapf:F, res:S }
 
-fn check_force_map_dep 
+fn check_force_map_dep 
+    <T:'static+Sized+Debug+PartialEq+Eq+Clone+Hash,
+     S:'static+Sized+Debug+PartialEq+Eq+Clone+Hash, 
+     F:Fn(&Art<T>, T)->S>
+    (st:&mut DCG, dep:&ForceMapDep<T,S,F>, loc:&Rc<Loc>) -> DCGRes 
+{
+    let node : &mut Node<T> = res_node_of_loc(st, loc) ;
+    match *node {
+        Node::Mut(ref nd) => 
+            DCGRes{changed:dep.res != (dep.mapf)
+                   (&Art{art:EnumArt::Loc(loc.clone())},
+                    (*nd.val).clone())},
+
+        // Dirtying cannot recompute a thunk's result; cleaning
+        // (below) recomputes it, and compares the mapped values then.
+        Node::Comp(_) => DCGRes{changed:true},
+
+        Node::Pure(_) | Node::Unused => 
+            unreachable!()
+    }
+}
+
+/// Begins cleaning the dependency on a thunk: its successors must be
+/// cleaned first, unless it has no result yet.
+fn clean_begin_force_map_dep
+    <T:'static+Sized+Debug+PartialEq+Eq+Clone+Hash,
+     S:'static+Sized+Debug+PartialEq+Eq+Clone+Hash, 
+     F:Fn(&Art<T>, T)->S>
+    (g:&RefCell<DCG>, dep:&ForceMapDep<T,S,F>, loc:&Rc<Loc>) -> CleanStep 
+{
+    {
+        let st = &mut *g.borrow_mut();
+        let node : &mut Node<T> = res_node_of_loc(st, loc) ;
+        match *node {
+            Node::Comp(ref nd) => if nd.res.is_some() {
+                return CleanStep::Succs(nd.succs.edges.clone())
+            },
+            Node::Mut(_) => return CleanStep::Done(check_force_map_dep(st, dep, loc)),
+            Node::Pure(_) | Node::Unused => unreachable!(),
+        }
+    } ;
+    let res : Rc<T> = loc_produce(g, loc) ;
+    CleanStep::Done(DCGRes{changed:dep.res != (dep.mapf)(&Art{art:EnumArt::Loc(loc.clone())}, (*res).clone())})
+}
+
+/// Finishes cleaning the dependency on a thunk, by mapping its
+/// (possibly re-evaluated) result.
+fn clean_end_force_map_dep
     <T:'static+Sized+Debug+PartialEq+Eq+Clone+Hash,
      S:'static+Sized+Debug+PartialEq+Eq+Clone+Hash, 
      F:Fn(&Art<T>, T)->S>
-    (st:&mut DCG, dep:&ForceMapDep<T,S,F>, loc:&Rc<Loc>) -> DCGRes 
+    (g:&RefCell<DCG>, dep:&ForceMapDep<T,S,F>, loc:&Rc<Loc>, reeval:bool) -> DCGRes 
 {
-    let node : &mut Node<T> = res_node_of_loc(st, loc) ;
-    match *node {
-        Node::Mut(ref nd) => 
-            DCGRes{changed:dep.res != (dep.mapf)
-                   (&Art{art:EnumArt::Loc(loc.clone())},
-                    nd.val.clone())},
-        
-        Node::Comp(_) | Node::Pure(_) | Node::Unused => 
-            unreachable!()
-    }
+    let res : Rc<T> = if reeval { loc_produce(g, loc) }
+                      else { loc_res(g, loc) } ;
+    DCGRes{changed:dep.res != (dep.mapf)(&Art{art:EnumArt::Loc(loc.clone())}, (*res).clone())}
 }
 
 impl <T:'static+Sized+Debug+PartialEq+Eq+Clone+Hash,
@@ -1033,9 +2022,13 @@ impl <T:'static+Sized+Debug+PartialEq+Eq+Clone+Hash,
     fn dirty(self:&Self, g:&mut DCG, loc:&Rc<Loc>) -> DCGRes {
         check_force_map_dep(g, self, loc)       
     }    
-    fn clean(self:&Self, g:&RefCell<DCG>, loc:&Rc<Loc>) -> DCGRes {
-        check_force_map_dep(&mut *g.borrow_mut(), self, loc)
+    fn clean_begin(self:&Self, g:&RefCell<DCG>, loc:&Rc<Loc>) -> CleanStep {
+        clean_begin_force_map_dep(g, self, loc)
+    }
+    fn clean_end(self:&Self, g:&RefCell<DCG>, loc:&Rc<Loc>, reeval:bool) -> DCGRes {
+        clean_end_force_map_dep(g, self, loc, reeval)
     }
+    fn res_typeid(self:&Self) -> Option<TypeId> { Some(TypeId::of::<T>()) }
 }
 
 impl <T:'static+Sized+Debug+PartialEq+Eq+Clone+Hash,
@@ -1048,55 +2041,70 @@ impl <T:'static+Sized+Debug+PartialEq+Eq+Clone+Hash,
   }    
 }
 
+impl <T:'static+Sized+Debug+PartialEq+Eq+Clone+Hash,
+      S:'static+Sized+Debug+PartialEq+Eq+Clone+Hash, F:Fn(&Art<T>, T)->S>
+    reflect::Reflect<reflect::Val> for ForceMapDep<T,S,F> 
+{
+  // The reflected value is the mapped value, which is the value
+  // that the observer depends on.
+  fn reflect(&self) -> reflect::Val { reflect::reflect_val(&self.res) }
+}
+
 
 
 impl <Res:'static+Sized+Debug+PartialEq+Eq+Clone+Hash>
   DCGDep for ForceDep<Res>
 {
+  fn res_typeid(self:&Self) -> Option<TypeId> { Some(TypeId::of::<Res>()) }
+
+  fn shares_res(self:&Self) -> bool { true }
+
   fn dirty(self:&Self, _g:&mut DCG, _loc:&Rc<Loc>) -> DCGRes {
       DCGRes{changed:true}
   }
 
-  fn clean(self:&Self, g:&RefCell<DCG>, loc:&Rc<Loc>) -> DCGRes {
-    let res_succs = { // Handle cases where there is no internal computation to re-compute:
+  fn clean_begin(self:&Self, g:&RefCell<DCG>, loc:&Rc<Loc>) -> CleanStep {
+    { // Handle cases where there is no internal computation to re-compute:
       let st = &mut *g.borrow_mut();
       let node : &mut Node<Res> = res_node_of_loc(st, loc) ;
       match *node {
         Node::Comp(ref nd) => {
-          match nd.res {
-            Some(ref res) => Some((res.clone(), nd.succs.clone ())),
-            None => None
-          }},
+          if nd.res.is_some() { return CleanStep::Succs(nd.succs.edges.clone()) }
+        },
         Node::Pure(_) => {
-          return DCGRes{changed:false}
+          return CleanStep::Done(DCGRes{changed:false})
         },
         Node::Mut(ref nd) => {
-          return DCGRes{changed:nd.val != self.res}
+          return CleanStep::Done(DCGRes{changed:nd.val != self.res})
         },
         _ => panic!("undefined")
       }
     } ;
     let none : Option<Loc> = None ;
-    match res_succs {
-      Some((res,succs)) => clean_comp(g, self, loc, res, succs),
-      None => {
-        dcg_effect_begin!(
-          reflect::trace::Effect::CleanEval,
-          none,
-          reflect::Succ{
-            loc:loc.reflect(),
-            dirty:true,
-            effect:reflect::Effect::Force,
-            value:reflect::Val::ValTODO,
-            is_dup:false, // XXX -- Actually: Not checked here.
-          }
-        );
-        let res = loc_produce( g, loc );
-        let changed = self.res != res ;
-        // TODO: changed to reflect::trace somehow?
-        dcg_effect_end!();
-        DCGRes{changed:changed}
+    dcg_effect_begin!(
+      reflect::trace::Effect::CleanEval,
+      none,
+      reflect::Succ{
+        loc:loc.reflect(),
+        dirty:true,
+        effect:reflect::Effect::Force,
+        value:self.reflect(),
+        is_dup:false, // XXX -- Actually: Not checked here.
       }
+    );
+    let res = loc_produce( g, loc );
+    let changed = self.res != res ;
+    // TODO: changed to reflect::trace somehow?
+    dcg_effect_end!();
+    CleanStep::Done(DCGRes{changed:changed})
+  }
+
+  fn clean_end(self:&Self, g:&RefCell<DCG>, loc:&Rc<Loc>, reeval:bool) -> DCGRes {
+    if reeval {
+      let result : Rc<Res> = loc_produce( g, loc ) ;
+      DCGRes{changed:result != self.res}
+    } else {
+      DCGRes{changed:with_loc_res(g, loc, |res:&Rc<Res>| res != &self.res)}
     }
   }
 }
@@ -1107,28 +2115,21 @@ fn revoke_succs<'x> (st:&mut DCG, src:&Rc<Loc>, succs:&Vec<Succ>) {
   //let mut succ_idx = 0;
   for succ in succs.iter() {
     dcg_effect!(reflect::trace::Effect::Remove, Some(src), succ);
-    let succ_node : &mut Box<GraphNode> = lookup_abs(st, &succ.loc) ;
+    let succ_node : &mut GraphNode = lookup_abs(st, &succ.loc) ;
     succ_node.preds_remove(src)
   }
 }
 
-fn loc_of_id(path:Rc<Path>,id:Rc<ArtId>) -> Rc<Loc> {
-  let hash = my_hash(&(&path,&id));
-  Rc::new(Loc{path:path,id:id,hash:hash})
-}
-
 fn get_succ<'r>(st:&'r DCG, src_loc:&Rc<Loc>, eff:Effect, tgt_loc:&Rc<Loc>) -> &'r Succ {
-  let nd = st.table.get(src_loc);
+  let nd = st.node(src_loc);
   let nd = match nd {
     None => panic!(""),
     Some(nd) => nd
   } ;    
-  for succ in nd.succs() {
-    if (succ.effect == eff) && (&succ.loc == tgt_loc) {
-      return succ
-    } else {}
-  } ;
-  panic!("tgt_loc is dangling in src_node.dem_succs")
+  match nd.succ(&eff, tgt_loc) {
+    Some(succ) => succ,
+    None => panic!("tgt_loc is dangling in src_node.dem_succs"),
+  }
 }
 
 // Implement "sharing" of the dirty bit.
@@ -1136,67 +2137,82 @@ fn get_succ<'r>(st:&'r DCG, src_loc:&Rc<Loc>, eff:Effect, tgt_loc:&Rc<Loc>) -> &
 // and mutating the dirty bit.
 fn get_succ_mut<'r>(st:&'r mut DCG, src_loc:&Rc<Loc>, eff:Effect, tgt_loc:&Rc<Loc>) -> &'r mut Succ {
   let nd = lookup_abs( st, src_loc );
-  for succ in nd.succs_mut().iter_mut() {
-    if (succ.effect == eff) && (&succ.loc == tgt_loc) {
-      return succ
-    } else {}
-  } ;
-  panic!("tgt_loc is dangling in src_node.dem_succs")
-}
-
-fn dirty_pred_observers(st:&mut DCG, loc:&Rc<Loc>) {
-    let pred_locs : Vec<(Rc<Loc>, Option<Rc<Box<DCGDep>>>)> = lookup_abs( st, loc ).preds_obs() ;
-    for (pred_loc, dep) in pred_locs {
+  match nd.succ_mut(&eff, tgt_loc) {
+    Some(succ) => succ,
+    None => panic!("tgt_loc is dangling in src_node.dem_succs"),
+  }
+}
+
+/// Work items for `dirty_preds`.
+enum DirtyWork {
+  /// Dirty the observer edges into this location.
+  Observers(Rc<Loc>),
+  /// Dirty this edge, from a predecessor of this location, unless its
+  /// dependency (if any) is unchanged.
+  Edge(Rc<Loc>, Effect, Rc<Loc>, Option<Rc<Box<DCGDep>>>),
+  /// End the extent of a dirtied edge, in the trace.
+  End,
+}
+
+/// Dirties edges, transitively along observer edges, using an
+/// explicit worklist rather than recursion (dirtying chains may be
+/// arbitrarily long).  Edges that are already dirty stop the
+/// traversal.
+fn dirty_preds(st:&mut DCG, mut work:Vec<DirtyWork>) {
+  while let Some(item) = work.pop() {
+    match item {
+      DirtyWork::End => dcg_effect_end!(),
+      DirtyWork::Observers(loc) => {
+        let pred_locs : Vec<(Rc<Loc>, Option<Rc<Box<DCGDep>>>)> = lookup_abs( st, &loc ).preds_obs() ;
+        // Reversed, so that edges are dirtied in order.
+        for (pred_loc, dep) in pred_locs.into_iter().rev() {
+          work.push(DirtyWork::Edge(pred_loc, Effect::Observe, loc.clone(), dep))
+        }
+      },
+      DirtyWork::Edge(pred_loc, effect, loc, dep) => {
         let stop : bool = match dep {
-            None => false,
-            Some(dep) => dep.dirty(st, loc).changed == false
+          None => false,
+          Some(dep) => dep.dirty(st, &loc).changed == false
         };
         let stop : bool = if stop { true } else {
-            // The stop bit communicates information from st for use below.
-            let succ = get_succ_mut(st, &pred_loc, Effect::Observe, &loc) ;
-            if succ.dirty { true } else {
-                assert!(&pred_loc != loc);
-                dcg_effect_begin!(reflect::trace::Effect::Dirty, Some(&pred_loc), succ);
-                replace(&mut succ.dirty, true);
-                false
-            }} 
+          // The stop bit communicates information from st for use below.
+          let succ = get_succ_mut(st, &pred_loc, effect, &loc) ;
+          if succ.dirty { true } else {
+            assert!(pred_loc != loc);
+            dcg_effect_begin!(reflect::trace::Effect::Dirty, Some(&pred_loc), succ);
+            succ.dirty = true;
+            false
+          }}
         ;
         if !stop {
-            dirty_pred_observers(st,&pred_loc);
-            dcg_effect_end!();
+          work.push(DirtyWork::End);
+          work.push(DirtyWork::Observers(pred_loc));
         } else { }
+      },
     }
+  }
+}
+
+fn dirty_pred_observers(st:&mut DCG, loc:&Rc<Loc>) {
+  dirty_preds(st, vec![DirtyWork::Observers(loc.clone())])
 }
 
 fn dirty_alloc(st:&mut DCG, loc:&Rc<Loc>) {
-  dirty_pred_observers(st, loc);
   let pred_locs : Vec<Rc<Loc>> = lookup_abs(st, loc).preds_alloc() ;
-  for pred_loc in pred_locs {
-    let stop : bool = {
-      // The stop bit communicates information from st for use below.
-      let succ = get_succ_mut(st, &pred_loc, Effect::Allocate, &loc) ;
-      if succ.dirty { true } else {
-        replace(&mut succ.dirty, true);
-        assert!(&pred_loc != loc);
-        dcg_effect_begin!(reflect::trace::Effect::Dirty, Some(&pred_loc), succ);
-        false
-      }} ;
-    if !stop {
-      dirty_pred_observers(st,&pred_loc);
-      dcg_effect_end!();
-    } else {  }
-  }
-  if false /* XXX Check make this better, as a statically/dynamically-set flag? */ {
-    wf::check_stack_is_clean(st)
-  }
+  let mut work = vec![] ;
+  for pred_loc in pred_locs.into_iter().rev() {
+    work.push(DirtyWork::Edge(pred_loc, Effect::Allocate, loc.clone(), None))
+  } ;
+  work.push(DirtyWork::Observers(loc.clone()));
+  dirty_preds(st, work)
 }
 
 /// Returns true if changed, false if unchanged.
 fn check_cell_change<T:'static+Eq+Debug> (st:&mut DCG, cell:AbsArt<T,Loc>, val:&T) -> bool {
     if let AbsArt::Loc(ref loc) = cell { 
         let node = res_node_of_loc::<T>( st, loc ) ;
-        match **node {
-            Node::Mut(ref mut nd) => { &nd.val != val }
+        match *node {
+            Node::Mut(ref mut nd) => { &*nd.val != val }
             _ => unreachable!(),
         }
     }
@@ -1208,12 +2224,12 @@ fn set_<T:'static+Eq+Debug> (st:&mut DCG, cell:AbsArt<T,Loc>, val:T) {
   if let AbsArt::Loc(ref loc) = cell { 
     let changed : bool = {
       let node = res_node_of_loc( st, loc ) ;
-      match **node {
+      match *node {
         Node::Mut(ref mut nd) => {
-          if nd.val == val {
+          if *nd.val == val {
             false
           } else {
-            replace(&mut nd.val, val) ;
+            nd.val = Rc::new(val) ;
             true
           }},
         _ => unreachable!(),
@@ -1263,9 +2279,16 @@ trait Adapton : Debug+PartialEq+Eq+Hash+Clone {
   
   /// Creates a mutable articulation.
   fn cell<T:Eq+Debug+Clone+Hash+'static> (self:&mut Self, Name, T) -> AbsArt<T,Self::Loc> ;
+
+  /// Creates (or shares) an immutable articulation, hash-consed by its value.
+  fn intern<T:Eq+Debug+Clone+Hash+'static> (self:&mut Self, T) -> AbsArt<T,Self::Loc> ;
   
   /// Mutates a mutable articulation.
   fn set<T:'static+Eq+Debug+Clone> (self:&mut Self, AbsArt<T,Self::Loc>, T) ;
+
+  /// Mutates a mutable articulation in place; the mutation reports
+  /// whether it changed the value.
+  fn update<T:'static+Eq+Debug+Clone, F:FnOnce(&mut T) -> bool> (self:&mut Self, AbsArt<T,Self::Loc>, F) ;
   
   /// Creates an articulated computation.
   fn thunk <Arg:Eq+Hash+Debug+Clone+'static,
@@ -1282,6 +2305,10 @@ trait Adapton : Debug+PartialEq+Eq+Hash+Clone {
   /// Demand & observe arts (all kinds): force
   fn force<T:Eq+Debug+Clone+Hash+'static> (g:&RefCell<DCG>, &AbsArt<T,Self::Loc>) -> T ;
 
+  /// Demand & observe arts (all kinds): force, borrowing the value
+  fn force_with<T:Eq+Debug+Clone+Hash+'static, R, F:FnOnce(&T) -> R>
+        (g:&RefCell<DCG>, &AbsArt<T,Self::Loc>, F) -> R ;
+
   /// Demand & observe arts (all kinds): force
   fn force_map<T:Eq+Debug+Clone+Hash+'static,
                S:Eq+Debug+Clone+Hash+'static, 
@@ -1297,16 +2324,18 @@ impl Adapton for DCG {
   fn new () -> DCG {
     let path = Rc::new(Path::Empty);
     let stack = Vec::new() ;
-    let table = HashMap::new ();
     DCG {
       flags : Flags {
         use_purity_optimization       : { match env::var("ADAPTON_NO_PURITY")  { Ok(_) => false, _ => true } },
         ignore_nominal_use_structural : { match env::var("ADAPTON_STRUCTURAL") { Ok(_) => true,  _ => false } },
         check_dcg_is_wf               : { match env::var("ADAPTON_CHECK_DCG")  { Ok(_) => true,  _ => false } },
+        verify_cache_hits             : { match env::var("ADAPTON_VERIFY")     { Ok(_) => true,  _ => false } },
         write_dcg                     : { match env::var("ADAPTON_WRITE_DCG")  { Ok(_) => true,  _ => false } },
         gmlog_dcg                     : { match env::var("ADAPTON_GMLOG_DCG")  { Ok(_) => true,  _ => false } },
       },
-      table : table,
+      nodes : PVec::new(),
+      locs  : PVec::new(),
+      ids   : PMap::new(),
       stack : stack,
       path  : path,
       dcg_count : 0,
@@ -1358,15 +2387,13 @@ impl Adapton for DCG {
           (Rc::new(ArtId::Structural(hash)), self.flags.use_purity_optimization) // Ignore the name; do hash-consing instead.
         }
       };            
-      let hash = my_hash(&(&path,&id));
-      let loc  = Rc::new(Loc{path:path,id:id,hash:hash})
-      ;
+      let loc = self.loc_of_id(path, id) ;
       let (do_dirty, do_set, succs, do_insert, is_fresh) =
-        if self.table.contains_key(&loc) {
-          let node : &Box<Node<T>> = res_node_of_loc(self, &loc) ;
-          match **node {
+        if self.node_id(&loc).is_some() {
+          let node : &Node<T> = res_node_of_loc(self, &loc) ;
+          match *node {
             Node::Mut(_)       => { (false, true,  None, false, false) }
-            Node::Comp(ref nd) => { (true,  false, Some(nd.succs.clone()),  false, false ) }
+            Node::Comp(ref nd) => { (true,  false, Some(nd.succs.edges.clone()),  false, false ) }
             Node::Pure(_)      => { (false, false, None, false, false) }
             Node::Unused       => unreachable!()
           }} else                 { (false, false, None, true, true ) } 
@@ -1400,7 +2427,7 @@ impl Adapton for DCG {
         reflect::Succ{
           loc:loc.reflect(), 
           effect:reflect::Effect::Alloc, 
-          value:reflect::Val::ValTODO, 
+          value:reflect::reflect_val(&val), 
           dirty:false,
           is_dup:false, // XXX -- Actually: Not checked here.
         }
@@ -1411,12 +2438,12 @@ impl Adapton for DCG {
       dcg_effect_end!();
       
       if do_insert {
-        let node = if is_pure { Node::Pure(PureNode{val:val.clone()}) } else {
+        let node = if is_pure { Node::Pure(PureNode{val:Rc::new(val.clone())}) } else {
           Node::Mut(MutNode{
-            preds:Vec::new(),
-            val:val.clone(),
+            preds:Edges::new(),
+            val:Rc::new(val.clone()),
           })} ;
-        self.table.insert(loc.clone(), Box::new(node));
+        self.insert_node(&loc, Rc::new(node));
       } ;
       if ! is_pure { match self.stack.last_mut() { 
         None => (),        
@@ -1432,13 +2459,78 @@ impl Adapton for DCG {
       AbsArt::Loc(loc)
     }
 
+  fn intern<T:Eq+Debug+Clone+Hash+'static> (self:&mut DCG, val:T) -> AbsArt<T,Self::Loc> {
+    wf::check_dcg(self);
+    // Pure nodes reside at the empty path, so that equal values share
+    // a node across namespaces.
+    let path = Rc::new(Path::Empty) ;
+    let mut hash = my_hash (&val) ;
+    let loc = loop {
+      let loc = self.loc_of_id(path.clone(), Rc::new(ArtId::Structural(hash)));
+      match self.node(&loc) {
+        None => break loc,
+        Some(node) => {
+          // If the node holds this value, share it.
+          if node.res_typeid() == TypeId::of::<T>() {
+            let res_nd: &Node<T> = unsafe { &*(node as *const GraphNode as *const Node<T>) } ;
+            match *res_nd {
+              Node::Pure(ref nd) if *nd.val == val => { return AbsArt::Loc(loc) },
+              _ => (),
+            }
+          } ;
+          // Otherwise, the hash collides with that of another
+          // node; so, rehash, and probe the next location.
+          hash = my_hash (&(hash, &val)) ;
+        }
+      }
+    } ;
+    // Pure nodes have no predecessors: neither this allocation, nor
+    // forcing the node, records an edge.
+    self.insert_node(&loc, Rc::new(Node::Pure(PureNode{val:Rc::new(val)})));
+    wf::check_dcg(self);
+    AbsArt::Loc(loc)
+  }
+
   fn set<T:'static+Eq+Debug> (self:&mut Self, cell:AbsArt<T,Self::Loc>, val:T) {
     wf::check_dcg(self);
     assert!( self.stack.is_empty() ); // => outer layer has control.
+    let cell = match cell { AbsArt::Loc(ref loc) => AbsArt::Loc(self.canonical_loc(loc)), cell => cell } ;
     set_(self, cell, val);
     wf::check_dcg(self);
   }
 
+  fn update<T:'static+Eq+Debug+Clone, F:FnOnce(&mut T) -> bool> (self:&mut Self, cell:AbsArt<T,Self::Loc>, f:F) {
+    wf::check_dcg(self);
+    assert!( self.stack.is_empty() ); // => outer layer has control.
+    if let AbsArt::Loc(ref loc) = cell {
+      let loc = &self.canonical_loc(loc) ;
+      // The dependencies of the cell's observers share its value (see
+      // `ForceDep`); replace them first, so that mutating the value in
+      // place need not copy it.  (A fork may still share it.)  The
+      // replacements report a change when cleaned, conservatively.
+      let sharing : Vec<Rc<Loc>> = lookup_abs( self, loc ).preds_obs().into_iter()
+        .map(|(pred_loc, _)| pred_loc)
+        .filter(|pred_loc| match self.node(pred_loc).and_then(|nd| nd.succ(&Effect::Observe, loc)) {
+          Some(succ) => succ.dep.shares_res(),
+          None => false,
+        }).collect() ;
+      for pred_loc in sharing.iter() {
+        get_succ_mut(self, pred_loc, Effect::Observe, loc).dep = Rc::new(Box::new(UpdatedCell))
+      } ;
+      let changed : bool = {
+        let node = res_node_of_loc( self, loc ) ;
+        match *node {
+          Node::Mut(ref mut nd) => f(Rc::make_mut(&mut nd.val)),
+          _ => unreachable!(),
+        }} ;
+      if changed {
+        dirty_alloc(self, loc);
+      }
+    }
+    else { panic!("{:?} is not a cell", cell) } ;
+    wf::check_dcg(self);
+  }
+
   fn thunk<Arg:Eq+Hash+Debug+Clone+'static,Spurious:'static+Clone,Res:Eq+Debug+Clone+Hash+'static>
     (self:&mut DCG,
      id:ArtIdChoice,
@@ -1461,13 +2553,32 @@ impl Adapton for DCG {
 
       ArtIdChoice::Structural => {
         wf::check_dcg(self);
-        let hash = my_hash (&(&prog_pt, &arg)) ;
-        let loc = loc_of_id(current_path(self),
-                            Rc::new(ArtId::Structural(hash)));
-        {   // If the node exists, return early.
-          let node = self.table.get_mut(&loc);
-          match node { None    => { },
-                       Some(_) => { return AbsArt::Loc(loc) }, // Nothing to do; it already exists.
+        let producer : Box<Producer<Res>> =
+          Box::new(App{prog_pt:prog_pt.clone(),
+                       fn_box:fn_box,
+                       arg:arg.clone(),
+                       spurious:spurious.clone()})
+          ;
+        let path = current_path(self) ;
+        let mut hash = my_hash (&(&prog_pt, &arg)) ;
+        let loc = loop {
+          let loc = self.loc_of_id(path.clone(), Rc::new(ArtId::Structural(hash)));
+          match self.node(&loc) {
+            None => break loc,
+            Some(node) => {
+              // If the node is this computation, return early: Nothing
+              // to do; it already exists.
+              if node.res_typeid() == TypeId::of::<Res>() {
+                let res_nd: &Node<Res> = unsafe { &*(node as *const GraphNode as *const Node<Res>) } ;
+                match *res_nd {
+                  Node::Comp(ref nd) if nd.producer.eq( &*producer ) => { return AbsArt::Loc(loc) },
+                  _ => (),
+                }
+              } ;
+              // Otherwise, the hash collides with that of another
+              // node; so, rehash, and probe the next location.
+              hash = my_hash (&(hash, &prog_pt, &arg)) ;
+            }
           }
         } ;
         // assert: node does not exist.
@@ -1481,29 +2592,22 @@ impl Adapton for DCG {
                    dirty:false};
             frame.succs.push((succ, None))
           }};
-        let producer : Box<Producer<Res>> =
-          Box::new(App{prog_pt:prog_pt,
-                       fn_box:fn_box,
-                       arg:arg.clone(),
-                       spurious:spurious.clone()})
-          ;
         let node : CompNode<Res> = CompNode{
-          preds:Vec::new(),
-          succs:Vec::new(),
+          preds:Edges::new(),
+          succs:Edges::new(),
           producer:producer,
           res:None,
         } ;
         //self.cnt.create += 1;
-        self.table.insert(loc.clone(),
-                          Box::new(Node::Comp(node)));
+        self.insert_node(&loc, Rc::new(Node::Comp(node)));
         wf::check_dcg(self);
         AbsArt::Loc(loc)
       },
 
       ArtIdChoice::Nominal(nm) => {
         wf::check_dcg(self);
-        let loc = loc_of_id(current_path(self),
-                            Rc::new(ArtId::Nominal(nm)));
+        let path = current_path(self) ;
+        let loc = self.loc_of_id(path, Rc::new(ArtId::Nominal(nm)));
         let producer : App<Arg,Spurious,Res> =
           App{prog_pt:prog_pt.clone(),
               fn_box:fn_box,
@@ -1511,16 +2615,16 @@ impl Adapton for DCG {
               spurious:spurious.clone(),
           }
         ;
-        let (do_dirty, do_insert, is_fresh) = { match self.table.get_mut( &loc ) {
+        let (do_dirty, do_insert, is_fresh) = { match self.node_mut( &loc ) {
           None => {
             // do_dirty=false; do_insert=true
             (false, true, true)
           },
           Some(node) => {
-            let node: &mut Box<GraphNode> = node ;
+            let node: &mut GraphNode = node ;
             assert_graphnode_res_type::<Res>(&loc, node);
-            let res_nd: &mut Box<Node<Res>> = unsafe { transmute::<_,_>( node ) } ;
-            match ** res_nd {
+            let res_nd: &mut Node<Res> = unsafe { &mut *(node as *mut GraphNode as *mut Node<Res>) } ;
+            match *res_nd {
               Node::Pure(_)=> unreachable!(),
               Node::Mut(_) => {
                 (true, true, false) // Todo: Do we need to preserve preds?
@@ -1582,7 +2686,7 @@ impl Adapton for DCG {
           reflect::Succ{
             loc:loc.reflect(),
             effect:reflect::Effect::Alloc,
-            value:reflect::Val::ValTODO,
+            value:reflect::reflect_val(&arg),
             dirty:false,
             is_dup:false, // XXX -- Actually: Not checked here.
           });
@@ -1599,12 +2703,12 @@ impl Adapton for DCG {
         }};
         if do_insert {
           let node : CompNode<Res> = CompNode{
-            preds:Vec::new(),
-            succs:Vec::new(),
+            preds:Edges::new(),
+            succs:Edges::new(),
             producer:Box::new(producer),
             res:None,
           } ;
-          self.table.insert(loc.clone(), Box::new(Node::Comp(node)));
+          self.insert_node(&loc, Rc::new(Node::Comp(node)));
           wf::check_dcg(self);
           AbsArt::Loc(loc)
         }
@@ -1627,17 +2731,56 @@ impl Adapton for DCG {
           AbsArt::Rc(ref v) => mapf(&Art{art:EnumArt::Rc(v.clone())}, 
                                     (**v).clone()),
           AbsArt::Loc(ref loc) => {
-              let cell_val : Option<T> = {
+              let loc = &g.borrow().canonical_loc(loc) ;
+              let (is_comp, cell_val) : (bool, Option<Rc<T>>) = {
                   let st : &mut DCG = &mut *g.borrow_mut();
                   let node : &mut Node<T> = res_node_of_loc(st, &loc) ;
                   match *node {
                       Node::Unused => unreachable!(),
-                      Node::Comp(_) => { None }
-                      Node::Pure(_) => { None }
-                      Node::Mut(ref nd)  => { Some(nd.val.clone()) }
+                      Node::Comp(_) => { (true, None) }
+                      Node::Pure(_) => { (false, None) }
+                      Node::Mut(ref nd)  => { (false, Some(nd.val.clone())) }
                   }
               } ;              
               match cell_val {
+                  None if is_comp => {
+                      // Case: We are forcing a thunk; so, we record
+                      // the mapped value, and the mapping function,
+                      // in the DCG.  Dirtying cannot recompute the
+                      // thunk, so it dirties the edge regardless;
+                      // cleaning recomputes the thunk, and prunes
+                      // when the mapped value is unchanged.
+                      let (val, is_dup, is_pure) : (Rc<T>, bool, bool) = force_loc(g, loc) ;
+                      let res = mapf(&Art{art:EnumArt::Loc(loc.clone())}, (*val).clone());
+                      let st : &mut DCG = &mut *g.borrow_mut() ;
+                      if !is_pure { match st.stack.last_mut() { None => (), Some(frame) => {
+                          if is_dup {
+                              // The frame already observes the thunk,
+                              // perhaps through a different mapping;
+                              // so, observe it fully instead.
+                              match frame.succs.get_mut(&Effect::Observe, loc) {
+                                  Some(&mut (ref mut succ, ref mut pred_dep)) => {
+                                      succ.dep = Rc::new(Box::new(ForceDep{res:val.clone()}));
+                                      *pred_dep = None;
+                                  },
+                                  None => unreachable!(),
+                              }
+                          } else {
+                              let dep : Rc<Box<DCGDep>> = Rc::new(Box::new(ForceMapDep{
+                                  raw:PhantomData,
+                                  mapf:mapf,
+                                  res:res.clone()}));
+                              let succ =
+                                  Succ{loc:loc.clone(),
+                                       dep:dep.clone(),
+                                       effect:Effect::Observe,
+                                       dirty:false};
+                              frame.succs.push((succ, Some(dep)));
+                          }
+                      }}} ;
+                      wf::check_dcg(st);
+                      res
+                  },
                   None => {
                       mapf(&Art{art:EnumArt::Loc(loc.clone())},
                            <DCG as Adapton>::force(g, art))
@@ -1646,19 +2789,19 @@ impl Adapton for DCG {
                       // Case: We _are_ forcing a cell; so, we record
                       // the mapped value, and the mapping function,
                       // in the DCG.
+                      let res = mapf(&Art{art:EnumArt::Loc(loc.clone())}, (*val).clone());
                       dcg_effect!(
                           // TODO-Now: Reflect the fact that we are doing a mapping here
                           reflect::trace::Effect::Force(reflect::trace::ForceCase::RefGet),
                           current_loc!(*g.borrow()),
                           reflect::Succ{
                               loc:loc.reflect(),
-                              value:reflect::Val::ValTODO,
+                              value:reflect::reflect_val(&res),
                               effect:reflect::Effect::Force,
                               dirty:false,
                               is_dup:false,
                           });
                       let st : &mut DCG = &mut *g.borrow_mut() ;
-                      let res = mapf(&Art{art:EnumArt::Loc(loc.clone())}, val.clone());
                       match st.stack.last_mut() { None => (), Some(frame) => {
                           let dep : Rc<Box<DCGDep>> = Rc::new(Box::new(ForceMapDep{
                               raw:PhantomData,
@@ -1692,89 +2835,8 @@ impl Adapton for DCG {
     match *art {
       AbsArt::Rc(ref v) => (**v).clone(),
       AbsArt::Loc(ref loc) => {
-        let (is_comp, is_dup, is_pure, cached_result) : (bool, bool, bool, Option<T>) = {
-          let st : &mut DCG = &mut *g.borrow_mut();
-          let is_pure_opt : bool = st.flags.use_purity_optimization ;
-          let is_dup : bool = match st.stack.last_mut() { None => false, Some(frame) => {
-              let mut is_dup = false; // XXX -- Actually: unknown and does not matter.
-              for &(ref succ, ref _pred_dep) in frame.succs.iter() { 
-                  if &succ.loc == loc && succ.effect == Effect::Observe 
-                  { is_dup = true }
-              };
-              is_dup
-          }};
-          let node : &mut Node<T> = res_node_of_loc(st, &loc) ;
-          match *node {
-            Node::Pure(ref mut nd) => (false, is_dup, true, Some(nd.val.clone())),
-            Node::Mut(ref mut nd)  => (false, is_dup, false, Some(nd.val.clone())),
-            Node::Comp(ref mut nd) => {
-              let is_pure = match *loc.id {
-                ArtId::Structural(_) => nd.succs.len() == 0 && is_pure_opt,
-                ArtId::Nominal(_)    => false } ;
-              (true, is_dup, is_pure, nd.res.clone()) },
-            _ => panic!("undefined")
-          }
-        } ;
-        let result = match cached_result {
-          None => {
-            assert!(is_comp);
-            dcg_effect_begin!(
-              reflect::trace::Effect::Force(reflect::trace::ForceCase::CompCacheMiss),
-              current_loc!(*g.borrow()),
-              reflect::Succ{
-                loc:loc.reflect(),
-                value:reflect::Val::ValTODO,
-                effect:reflect::Effect::Force,
-                dirty:false,
-                is_dup:is_dup,
-              }
-            );
-            assert_eq!(is_dup, false);
-            let res = loc_produce(g, &loc);
-            dcg_effect_end!();
-            res
-          },
-          Some(ref res) => {
-            if is_comp {
-              dcg_effect_begin!(
-                reflect::trace::Effect::Force(reflect::trace::ForceCase::CompCacheHit),
-                current_loc!(*g.borrow()),
-                reflect::Succ{
-                  loc:loc.reflect(),
-                  value:reflect::Val::ValTODO,
-                  effect:reflect::Effect::Force,
-                  dirty:false,
-                  is_dup:is_dup,
-                }
-              );
-              let _ = ForceDep{res:res.clone()}.clean(g, &loc) ;
-              dcg_effect_end!();
-              let st : &mut DCG = &mut *g.borrow_mut();
-              let node : &mut Node<T> = res_node_of_loc(st, &loc) ;
-              match *node {
-                Node::Comp(ref nd) => match nd.res {
-                  None => unreachable!(),
-                  Some(ref res) =>
-                    // Testing: Reached by `pure_caching` tests
-                    res.clone()
-                },
-                _ => unreachable!(),
-              }}
-            else {
-              dcg_effect!(
-                reflect::trace::Effect::Force(reflect::trace::ForceCase::RefGet),
-                current_loc!(*g.borrow()),
-                reflect::Succ{
-                  loc:loc.reflect(),
-                  value:reflect::Val::ValTODO,
-                  effect:reflect::Effect::Force,
-                  dirty:false,
-                  is_dup:is_dup,
-                });
-              res.clone()
-            }
-          }
-        } ;
+        let loc = &g.borrow().canonical_loc(loc) ;
+        let (result, is_dup, is_pure) : (Rc<T>, bool, bool) = force_loc(g, loc) ;
         let st : &mut DCG = &mut *g.borrow_mut() ;
         if !is_dup && !is_pure { match st.stack.last_mut() { None => (), Some(frame) => {
           let succ =
@@ -1785,7 +2847,39 @@ impl Adapton for DCG {
           frame.succs.push((succ, None));
         }}} ;
         wf::check_dcg(st);
-        result
+        (*result).clone()
+      }
+    }}
+
+  fn force_with<T:'static+Eq+Debug+Clone+Hash, R, F:FnOnce(&T) -> R>
+    (g:&RefCell<DCG>, art:&AbsArt<T,Self::Loc>, f:F) -> R
+  {
+    {
+      let st : &mut DCG = &mut *g.borrow_mut();
+      wf::check_dcg(st);
+      drop(st)
+    }
+    match *art {
+      AbsArt::Rc(ref v) => f(&**v),
+      AbsArt::Loc(ref loc) => {
+        let loc = &g.borrow().canonical_loc(loc) ;
+        let (is_dup, is_pure) = force_loc_clean::<T>(g, loc) ;
+        let has_frame = ! g.borrow().stack.is_empty() ;
+        if !is_dup && !is_pure && has_frame {
+          // The edge's dependency shares the result with the node.
+          let result : Rc<T> = loc_res(g, loc) ;
+          let st : &mut DCG = &mut *g.borrow_mut() ;
+          match st.stack.last_mut() { None => (), Some(frame) => {
+            let succ =
+              Succ{loc:loc.clone(),
+                   dep:Rc::new(Box::new(ForceDep{res:result})),
+                   effect:Effect::Observe,
+                   dirty:false};
+            frame.succs.push((succ, None));
+          }}
+        } ;
+        wf::check_dcg(&mut *g.borrow_mut());
+        with_loc_res(g, loc, |res:&Rc<T>| f(&**res))
       }
     }}
 }
@@ -1859,6 +2953,16 @@ impl<T:Debug> Debug for EnumArt<T> {
 
 impl<T:Eq> Eq for EnumArt<T> { }
 
+impl<T:Debug> reflect::ReflectVal for Art<T> {
+  fn reflect_val(&self) -> reflect::Val {
+    match self.art {
+      EnumArt::Rc(ref rc)   => reflect::reflect_val(&**rc),
+      EnumArt::Loc(ref loc) => reflect::Val::Art(loc.reflect(), reflect::ArtContent::Unknown),
+      EnumArt::Force(_)     => reflect::parse_val::parse_val(self),
+    }
+  }
+}
+
 trait Force<T> {
   fn force(&self) -> T;
   fn copy(self:&Self) -> Box<Force<T>>;
@@ -1899,11 +3003,7 @@ impl<A:Hash+Clone+Eq+Debug+'static,S:Clone+'static,T:'static>
     & self.id
   }
   fn hash_u64(&self) -> u64 {
-    let mut hasher = DefaultHasher::new();
-    self.id.hash( &mut hasher );
-    self.prog_pt.hash( &mut hasher );
-    self.arg.hash( &mut hasher );
-    hasher.finish()
+    my_hash(&(&self.id, &self.prog_pt, &self.arg))
   }
   fn eq (&self, other:&Force<T>) -> bool {    
     if   &self.id      == other.id()
@@ -1956,7 +3056,7 @@ pub fn name_unit () -> Name {
 pub fn name_pair (n1:Name, n2:Name) -> Name {
   let h = my_hash( &(n1.hash,n2.hash) ) ;
   let p = NameSym::Pair(n1.symbol, n2.symbol) ;
-  Name{ hash:h, symbol:Rc::new(p) }
+  Name{ hash:h, symbol:intern_namesym(p) }
 }
 
 /// Create a name from a hash value.
@@ -1965,37 +3065,56 @@ pub fn name_pair (n1:Name, n2:Name) -> Name {
 /// get reflection to type-check.  We should think of ways to avoid
 /// using this in the future.
 pub fn name_of_hash64(h:u64) -> Name {
-  // TODO: Get rid of need for Rc here; 
-  // Rc should be optional in names?
-  Name{ hash:h, symbol:Rc::new(NameSym::Hash64) }
+  Name{ hash:h, symbol:intern_namesym(NameSym::Hash64(h)) }
+}
+
+/// Create a name from the hash of a value.  Names of equal values
+/// are equal; names of distinct values are distinct, up to hash
+/// collisions.
+pub fn name_of_hash<T:Hash> (x:&T) -> Name {
+  name_of_hash64(my_hash(x))
+}
+
+/// Create a name from a `u64`
+pub fn name_of_u64 (u:u64) -> Name {
+  let h = my_hash(&u) ;
+  let s = NameSym::U64(u) ;
+  Name{ hash:h, symbol:intern_namesym(s) }
+}
+
+/// Create a name from a byte string
+pub fn name_of_bytes (b:&[u8]) -> Name {
+  let h = my_hash(&b) ;
+  let s = NameSym::Bytes(b.to_vec()) ;
+  Name{ hash:h, symbol:intern_namesym(s) }
 }
 
 /// Create a name from a `usize`
 pub fn name_of_usize (u:usize) -> Name {
   let h = my_hash(&u) ;
   let s = NameSym::Usize(u) ;
-  Name{ hash:h, symbol:Rc::new(s) }
+  Name{ hash:h, symbol:intern_namesym(s) }
 }
 
 /// Create a name from a `isize`
 pub fn name_of_isize (i:isize) -> Name {
   let h = my_hash(&i) ;
   let s = NameSym::Isize(i) ;
-  Name{ hash:h, symbol:Rc::new(s) }
+  Name{ hash:h, symbol:intern_namesym(s) }
 }
 
 /// Create a name from a `string`
 pub fn name_of_string (s:String) -> Name {
   let h = my_hash(&s);
-  let s = NameSym::String(s) ;
-  Name{ hash:h, symbol:Rc::new(s) }
+  let s = NameSym::String(NameStr::Owned(s)) ;
+  Name{ hash:h, symbol:intern_namesym(s) }
 }
 
 /// Create a name from a `str`
 pub fn name_of_str (s:&'static str) -> Name {
   let h = my_hash(&s);
-  let s = NameSym::String(s.to_string()) ;
-  Name{ hash:h, symbol:Rc::new(s) }
+  let s = NameSym::String(NameStr::Static(s)) ;
+  Name{ hash:h, symbol:intern_namesym(s) }
 }
 
 /// Create two names from one
@@ -2003,9 +3122,9 @@ pub fn name_fork (n:Name) -> (Name, Name) {
   let h1 = my_hash( &(&n, 11111111) ) ; // TODO-Later: make this hashing better.
   let h2 = my_hash( &(&n, 22222222) ) ;
   ( Name{ hash:h1,
-          symbol:Rc::new(NameSym::ForkL(n.symbol.clone())) } ,
+          symbol:intern_namesym(NameSym::ForkL(n.symbol.clone())) } ,
     Name{ hash:h2,
-          symbol:Rc::new(NameSym::ForkR(n.symbol)) } )    
+          symbol:intern_namesym(NameSym::ForkR(n.symbol)) } )    
 }
 
 /// Create three names from one
@@ -2027,6 +3146,38 @@ pub fn name_fork4 (n:Name)
   (n1,n2,n3,n4)
 }  
 
+/// Create `k` names from one.  As with `name_fork3` and `name_fork4`,
+/// each name but the last is the left fork of the remaining name.
+pub fn name_fork_n (n:Name, k:usize) -> Vec<Name> {
+  if k == 0 { return vec![] } ;
+  let mut gen = NameGen::new(n);
+  let mut names : Vec<Name> = (&mut gen).take(k - 1).collect();
+  names.push(gen.rest);
+  names
+}
+
+/// An endless, deterministic supply of fresh names, forked from a
+/// parent name.  The `i`th name generated is the `i`th name of
+/// `name_fork_n(parent, k)`, for any `k > i + 1`.
+#[derive(Clone,Debug)]
+pub struct NameGen {
+  rest : Name,
+}
+
+impl NameGen {
+  /// A generator of names forked from `parent`.
+  pub fn new (parent:Name) -> NameGen { NameGen{ rest:parent } }
+}
+
+impl Iterator for NameGen {
+  type Item = Name;
+  fn next (&mut self) -> Option<Name> {
+    let (n, rest) = name_fork(self.rest.clone());
+    self.rest = rest;
+    Some(n)
+  }
+}
+
 /// Creates or re-enters a given namespace; performs the given computation there.
 pub fn ns<T,F> (n:Name, body:F) -> T
   where F:FnOnce() -> T {
@@ -2074,6 +3225,26 @@ pub fn cell<T:Hash+Eq+Debug+Clone+'static> (n:Name, val:T) -> Art<T> {
   })
 }
 
+/// Hash-conses an immutable value: creates an `Art<T>` whose content
+/// never changes, and which shares its DCG node with every other
+/// interned value that is equal to it (and hence, interned arts are
+/// equal iff their values are).  Unlike for a `cell`, no name is
+/// needed, and neither interning the value nor forcing the art
+/// records a dependency, so the art never dirties its observers.
+/// With the naive engine, this is `put`.
+pub fn intern<T:Hash+Eq+Debug+Clone+'static> (val:T) -> Art<T> {
+  GLOBALS.with(|g| {
+    match g.borrow().engine {
+      Engine::DCG(ref dcg) => {
+        if
+          let AbsArt::Loc(loc) = (dcg.borrow_mut()).intern(val) {
+            Art{art:EnumArt::Loc(loc)} }
+        else { unreachable!() } }
+      Engine::Naive => Art{art:EnumArt::Rc(Rc::new(val))}
+    }
+  })
+}
+
 /// Mutates a mutable articulation.
 pub fn set<T:'static+Eq+Debug+Clone> (a:&Art<T>, val:T) {
   match (*a).art {
@@ -2092,6 +3263,33 @@ pub fn set<T:'static+Eq+Debug+Clone> (a:&Art<T>, val:T) {
   }
 }
 
+/// Mutates a mutable articulation in place, without cloning its
+/// value.  The closure `f` mutates the value, and returns `false` to
+/// report that the value is unchanged, in which case the
+/// articulation's observers are not dirtied.
+///
+/// The closure runs while the engine's DCG is mutably borrowed, so it
+/// must not use the engine: forcing, allocating or mutating any art
+/// from within it panics (as does calling `update` from within a
+/// thunk).  Panics if the engine in use is `Naive`, which has no
+/// mutable articulations.
+pub fn update<T:'static+Eq+Debug+Clone, F:FnOnce(&mut T) -> bool> (a:&Art<T>, f:F) {
+  match (*a).art {
+    EnumArt::Rc(_)    => { panic!("update: Cannot mutate immutable Rc articulation; use an DCG cell instead") },
+    EnumArt::Force(_) => { panic!("update: Cannot mutate immutable Force articulation; use an DCG cell instead") },
+    EnumArt::Loc(ref l) => {
+      GLOBALS.with(|g| {
+        match g.borrow().engine {
+          Engine::Naive => { panic!("update: Cannot mutate a DCG cell under the Naive engine; use the DCG engine that allocated it") },
+          Engine::DCG(ref dcg) => {
+            (dcg.borrow_mut()).update(AbsArt::Loc(l.clone()), f)
+          }
+        }
+      })
+    }
+  }
+}
+
 /// Allocates a thunk, an `Art<T>` that consists of a suspended
 /// computation that produces a value of type `T`.
 ///
@@ -2168,6 +3366,26 @@ pub fn force<T:Hash+Eq+Debug+Clone+'static> (a:&Art<T>) -> T {
   }
 }
 
+/// Demands and observes the value of an `&Art<T>`, like `force`,
+/// but borrows the value for the function `f`, rather than returning
+/// a clone of it.  Records the same dependency as `force`, which
+/// shares the value with the DCG rather than copying it, so that
+/// `force_with` never clones the value.  `f` must not use the engine.
+pub fn force_with<T:Hash+Eq+Debug+Clone+'static, R, F:FnOnce(&T) -> R> (a:&Art<T>, f:F) -> R {
+  match a.art {
+    EnumArt::Force(ref frc) => f(&frc.force()),
+    EnumArt::Rc(ref rc) => f(&**rc),
+    EnumArt::Loc(ref loc) => {
+      GLOBALS.with(|g| {
+        match g.borrow().engine {
+          Engine::DCG(ref dcg_refcell) => 
+            <DCG as Adapton>::force_with(dcg_refcell, &AbsArt::Loc(loc.clone()), f),
+          Engine::Naive => panic!("cannot force a non-naive location with the naive engine")
+      }})
+    }
+  }
+}
+
 /// Demands and observes the value of an `&Art<T>`, returning a
 /// (cloned) value of type `S`, mapped by function `mapf`.
 ///
@@ -2199,6 +3417,36 @@ pub fn force_map<T:Hash+Eq+Debug+Clone+'static,
     }
 }
 
+/// Returns the current value of an `&Art<T>`, without observing it:
+/// `peek` neither forces the art nor records a dependency on it (nor
+/// traces a `Force` effect).  For a cell, returns its value; for a
+/// thunk, returns its cached result, which is `None` if the thunk
+/// has not been evaluated (and may be stale, if it is dirty).
+pub fn peek<T:Hash+Eq+Debug+Clone+'static> (a:&Art<T>) -> Option<T> {
+  match a.art {
+    EnumArt::Force(_) => None,
+    EnumArt::Rc(ref rc) => Some((&**rc).clone()),
+    EnumArt::Loc(ref loc) => {
+      GLOBALS.with(|g| {
+        match g.borrow().engine {
+          Engine::DCG(ref dcg_refcell) => {
+            let st : &DCG = &*dcg_refcell.borrow() ;
+            let loc = &st.canonical_loc(loc) ;
+            let node : &Node<T> = res_node_of_loc_ref(st, loc) ;
+            match *node {
+              Node::Comp(ref nd) => nd.res.as_ref().map(|res| (**res).clone()),
+              Node::Pure(ref nd) => Some((*nd.val).clone()),
+              Node::Mut(ref nd)  => Some((*nd.val).clone()),
+              Node::Unused       => None,
+            }
+          },
+          Engine::Naive => panic!("cannot peek at a non-naive location with the naive engine")
+        }
+      })
+    }
+  }
+}
+
 /// Operations that monitor and alter the active engine.  Incremental
 /// applications should not use these operations directly.
 pub mod manage {
@@ -2224,7 +3472,69 @@ pub mod manage {
     });
     return engine
   }
-  
+
+  /// Forks the engine in use, without switching to the fork.  The fork
+  /// begins with the DCG of the engine in use (sharing its nodes, which
+  /// each side copies on its first write), and afterwards, changes to
+  /// either DCG leave the other untouched: to evaluate speculatively,
+  /// switch to the fork (via `use_engine`), and later switch back to
+  /// discard it, or keep using it to adopt it.  Arts allocated before
+  /// the fork are valid in both.  (See also `speculate`.)
+  ///
+  /// Panics if called by a thunk: only the outer layer may fork.
+  pub fn fork_engine () -> Engine {
+    GLOBALS.with(|g| {
+      match g.borrow().engine {
+        Engine::DCG(ref dcg) => {
+          let dcg = dcg.borrow() ;
+          assert!( dcg.stack.is_empty() ); // => outer layer has control.
+          Engine::DCG(RefCell::new(dcg.clone()))
+        },
+        Engine::Naive => Engine::Naive,
+      }})
+  }
+
+  /// Runs `body` in a fork of the engine in use (see `fork_engine`),
+  /// and then switches back to the engine in use, which `body` leaves
+  /// untouched.  Returns the result of `body`, and the fork, which the
+  /// caller may adopt (via `use_engine`), or drop.
+  pub fn speculate<T,F> (body:F) -> (T, Engine)
+    where F:FnOnce() -> T
+  {
+    let mut original = RestoreEngine(Some(use_engine(fork_engine()))) ;
+    let x = body() ;
+    let fork = use_engine(original.0.take().unwrap()) ;
+    (x, fork)
+  }
+
+  /// Switches back to the engine that it holds (if any) when dropped,
+  /// e.g., when the body of `speculate` panics.
+  struct RestoreEngine(Option<Engine>);
+
+  impl Drop for RestoreEngine {
+    fn drop(&mut self) {
+      if let Some(engine) = self.0.take() { drop(use_engine(engine)) }
+    }
+  }
+
+  /// Switch to using the given hash function for names, locations
+  /// and structural identities; returns the one that was in use.
+  ///
+  /// Panics if the function in use has already hashed a name or an
+  /// art (on this thread), and `hash_fn` differs from it: hashes from
+  /// different functions do not agree, so names and arts from before
+  /// the switch would not match those after it.
+  pub fn use_hash_fn (hash_fn: HashFn) -> HashFn {
+    HASH_FN.with(|h| {
+      let (old, used) = h.get();
+      if used && old != hash_fn {
+        panic!("use_hash_fn: cannot switch from {:?} to {:?} after hashing names or arts", old, hash_fn)
+      } ;
+      h.set((hash_fn, used));
+      old
+    })
+  }
+
   /// alias for `use_engine`
   pub fn init_engine (engine: Engine) -> Engine {
     use_engine(engine)
@@ -2247,6 +3557,19 @@ pub mod manage {
         Engine::Naive  => false
       }})
   }
+
+  pub use super::wf::WfViolation;
+
+  /// Checks the well-formedness invariants of the current DCG, and
+  /// returns every violation found, rather than panicking at the
+  /// first one.  The `Naive` engine has no DCG, and no violations.
+  pub fn validate_dcg () -> Vec<WfViolation> {
+    GLOBALS.with(|g| {
+      match g.borrow().engine {
+        Engine::DCG(ref dcg) => super::wf::validate(&*dcg.borrow()),
+        Engine::Naive        => vec![],
+      }})
+  }
 }
 
 // - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - 
@@ -2254,7 +3577,7 @@ pub mod manage {
 /// Well-formedness tests; for documentation and for debugging.
 ///
 mod wf {
-  use std::collections::HashMap;
+  use std::collections::HashSet;
   use std::rc::Rc;
   use std::io::BufWriter;
   use std::io::Write;
@@ -2263,64 +3586,134 @@ mod wf {
 
   use super::*;
 
-  #[derive(Eq,PartialEq,Clone)]
-  enum NodeStatus {
-    Dirty, Clean, Unknown
+  /// A violation of the DCG's well-formedness invariants, as reported
+  /// by `manage::validate_dcg`.  Locations are reflected (see
+  /// `reflect::Loc`).
+  #[derive(Debug,Clone,Eq,PartialEq,Hash)]
+  pub enum WfViolation {
+    /// Location `loc` is referenced by the edges or the stack frame of
+    /// `from`, but has no node in the table.
+    DanglingLoc{ from:reflect::Loc, loc:reflect::Loc },
+    /// The edge `src --effect--> tgt` has no matching predecessor entry in `tgt`.
+    SuccWithoutPred{ src:reflect::Loc, effect:reflect::Effect, tgt:reflect::Loc },
+    /// The predecessor entry `src --effect--> tgt` in `tgt` has no matching edge in `src`.
+    PredWithoutSucc{ src:reflect::Loc, effect:reflect::Effect, tgt:reflect::Loc },
+    /// The stack frame for `frame` reaches the dirty edge `src --effect--> tgt`.
+    DirtyStackEdge{ frame:reflect::Loc, src:reflect::Loc, effect:reflect::Effect, tgt:reflect::Loc },
+    /// Node `loc` has a dirty successor edge, but its observer `pred`
+    /// observes it by a clean edge.
+    CleanPredOfDirty{ pred:reflect::Loc, loc:reflect::Loc },
+    /// The dependency on the edge `src --effect--> tgt` expects a
+    /// result type other than that of the node at `tgt`.
+    TypeMismatch{ src:reflect::Loc, effect:reflect::Effect, tgt:reflect::Loc },
+  }
+
+  fn has_succ (st:&DCG, src:&Rc<Loc>, eff:&Effect, tgt:&Rc<Loc>) -> bool {
+    match st.node(src) {
+      Some(node) => node.succ(eff, tgt).is_some(),
+      None => false,
+    }
   }
 
-  type Cs = HashMap<Rc<Loc>, NodeStatus> ;
-
-  fn add_constraint (cs:&mut Cs, loc:&Rc<Loc>, new_status: NodeStatus)
-  {
-    let old_status = match
-      cs.get(loc) { None => NodeStatus::Unknown,
-                    Some(x) => (*x).clone() } ;
-    match (old_status, new_status) {
-      (NodeStatus::Clean, NodeStatus::Dirty) |
-      (NodeStatus::Dirty, NodeStatus::Clean) => {
-        panic!("{:?}: Constrained to be both clean and dirty: Inconsistent status => DCG is not well-formed.")
-      },
-      (NodeStatus::Unknown, new_status) => { cs.insert(loc.clone(), new_status); () },
-      (old_status, NodeStatus::Unknown) => { cs.insert(loc.clone(), old_status); () },
-      (ref old_status, ref new_status) if old_status == new_status => { },
-      _ => unreachable!(),
-    }
+  fn has_pred (node:&GraphNode, src:&Rc<Loc>, eff:&Effect) -> bool {
+    node.preds_contains(eff, src)
   }
 
-  // Constrains loc and all predecessors (transitive) to be dirty
-  fn dirty (st:&DCG, cs:&mut Cs, loc:&Rc<Loc>) {
-    add_constraint(cs, loc, NodeStatus::Dirty) ;
-    let node = match st.table.get(loc) { Some(x) => x, None => panic!("") } ;
-    for (pred,_) in node.preds_obs () {
-      // Todo: Assert that pred has a dirty succ edge that targets loc
-      let succ = super::get_succ(st, &pred, super::Effect::Observe, loc) ;
-      if succ.dirty {} else {
-        debug_dcg(st);
-        write_next_dcg(st, None);
-        panic!("Expected dirty edge, but found clean edge: {:?} --Observe--dirty:!--> {:?}", &pred, loc);
-      } ; // The edge is dirty.
-      dirty(st, cs, &pred)                
+  /// Checks the successors of a stack frame, and everything they
+  /// (transitively) reach, for dirty edges.
+  fn check_frame (st:&DCG, frame:&Frame, vs:&mut Vec<WfViolation>) {
+    let mut visited : HashSet<Rc<Loc>> = HashSet::new();
+    let mut todo : Vec<(Rc<Loc>, Succ)> =
+      frame.succs.iter().map(|&(ref succ, _)| (frame.loc.clone(), succ.clone())).collect();
+    while let Some((src, succ)) = todo.pop() {
+      if succ.dirty {
+        vs.push(WfViolation::DirtyStackEdge{
+          frame:frame.loc.reflect(), src:src.reflect(),
+          effect:succ.effect.reflect(), tgt:succ.loc.reflect() })
+      } ;
+      if visited.contains(&succ.loc) { continue } ;
+      visited.insert(succ.loc.clone());
+      match st.node(&succ.loc) {
+        // Dangling targets are reported by the table checks.
+        None => (),
+        Some(node) => if node.succs_def () {
+          for s in node.succs () { todo.push((succ.loc.clone(), s.clone())) }
+        },
+      }
     }
   }
 
-  // Constrains loc and all successors (transitive) to be clean
-  fn clean (st:&DCG, cs:&mut Cs, loc:&Rc<Loc>) {
-    add_constraint(cs, loc, NodeStatus::Clean) ;
-    let node = match st.table.get(loc) {
-      Some(x) => x,
-      None => { panic!("dangling: {:?}", loc) }
+  /// Checks every well-formedness invariant of the DCG, and returns
+  /// every violation found (in no particular order).
+  pub fn validate (st:&DCG) -> Vec<WfViolation> {
+    let mut vs = Vec::new();
+    for frame in st.stack.iter() {
+      if st.node(&frame.loc).is_none() {
+        vs.push(WfViolation::DanglingLoc{from:frame.loc.reflect(), loc:frame.loc.reflect()})
+      } ;
+      for &(ref succ, _) in frame.succs.iter() {
+        if st.node(&succ.loc).is_none() {
+          vs.push(WfViolation::DanglingLoc{from:frame.loc.reflect(), loc:succ.loc.reflect()})
+        }
+      } ;
+      check_frame(st, frame, &mut vs);
     } ;
-    if ! node.succs_def () { return } ;
-    for succ in node.succs () {
-      let succ = super::get_succ(st, loc, super::Effect::Observe, &succ.loc) ;
-      assert!( ! succ.dirty ); // The edge is clean.
-      clean(st, cs, &succ.loc)
-    }
+    for (loc, node) in st.table() {
+      // Successor edges: matching predecessors, agreeing result types.
+      if node.succs_def () {
+        for succ in node.succs () {
+          match st.node(&succ.loc) {
+            None => vs.push(WfViolation::DanglingLoc{from:loc.reflect(), loc:succ.loc.reflect()}),
+            Some(tgt) => {
+              if ! has_pred(tgt, loc, &succ.effect) {
+                vs.push(WfViolation::SuccWithoutPred{
+                  src:loc.reflect(), effect:succ.effect.reflect(), tgt:succ.loc.reflect() })
+              } ;
+              match succ.dep.res_typeid () {
+                Some(t) if t != tgt.res_typeid () =>
+                  vs.push(WfViolation::TypeMismatch{
+                    src:loc.reflect(), effect:succ.effect.reflect(), tgt:succ.loc.reflect() }),
+                _ => (),
+              }
+            }
+          }
+        }
+      } ;
+      if ! node.preds_def () { continue } ;
+      // Predecessor entries: matching successor edges.
+      let preds =
+        node.preds_obs().into_iter().map(|(pred, _)| (Effect::Observe, pred)).chain(
+          node.preds_alloc().into_iter().map(|pred| (Effect::Allocate, pred)));
+      for (eff, pred) in preds {
+        if st.node(&pred).is_none() {
+          vs.push(WfViolation::DanglingLoc{from:loc.reflect(), loc:pred.reflect()})
+        } else if ! has_succ(st, &pred, &eff, loc) {
+          vs.push(WfViolation::PredWithoutSucc{
+            src:pred.reflect(), effect:eff.reflect(), tgt:loc.reflect() })
+        }
+      } ;
+      // Dirty invariant: if this node has a dirty edge, then its
+      // observers observe it by dirty edges.
+      let is_dirty = node.succs_def () && node.succs().iter().any(|succ| succ.dirty) ;
+      if is_dirty {
+        for (pred, _) in node.preds_obs () {
+          let pred_is_clean = match st.node(&pred) {
+            Some(p) if p.succs_def () =>
+              p.succs().iter().any(|succ| succ.effect == Effect::Observe && &succ.loc == loc && !succ.dirty),
+            _ => false,
+          } ;
+          if pred_is_clean {
+            vs.push(WfViolation::CleanPredOfDirty{pred:pred.reflect(), loc:loc.reflect()})
+          }
+        }
+      }
+    } ;
+    vs
   }
 
   pub fn check_dcg (st:&mut DCG) {
     if st.flags.write_dcg {
-      let dcg_hash = my_hash(format!("{:?}",st.table)); // XXX: This assumes that the table's debugging string identifies it uniquely
+      let dcg_hash = my_hash(reflect::string_of_dcg(&st.reflect()));
       if dcg_hash != st.dcg_hash {
         println!("adapton: dcg #{} hash: {:?}", st.dcg_count, dcg_hash);
         st.dcg_hash = dcg_hash;
@@ -2330,20 +3723,23 @@ mod wf {
       }
     } ;
     if st.flags.check_dcg_is_wf {
-      let mut cs = HashMap::new() ;
-      for frame in st.stack.iter() {
-        clean(st, &mut cs, &frame.loc)
+      let vs = validate(st) ;
+      if ! vs.is_empty () {
+        debug_dcg(st);
+        write_next_dcg(st, None);
+        panic!("DCG is not well-formed: {:?}", vs)
       }
-      for (loc, node) in &st.table {
-        if ! node.succs_def () { continue } ;
-        for succ in node.succs () {
-          if succ.dirty {
-            dirty(st, &mut cs, loc)
-          }
-        }
-      }        
     }}
 
+  /// The nodes of the DCG, with their locations, sorted by their
+  /// locations (as `reflect::write_loc` writes them).
+  fn sorted_table (st:&DCG) -> Vec<(&Rc<Loc>, &GraphNode)> {
+    let mut table : Vec<(String, (&Rc<Loc>, &GraphNode))> =
+      st.table().map(|(loc, node)| (reflect::string_of_loc(&loc.reflect()), (loc, node))).collect();
+    table.sort_by(|a, b| a.0.cmp(&b.0));
+    table.into_iter().map(|(_, entry)| entry).collect()
+  }
+
   pub fn write_next_dcg (st:&DCG, num:Option<usize>) {
     let name = match num {
       None => format!("adapton-dcg.dot"),
@@ -2363,7 +3759,7 @@ mod wf {
         writeln!(&mut writer, "\"{:?}\" -> \"{:?}\" [color=blue,weight=10,penwidth=10];", &frame.loc, &succ.0.loc).unwrap();
       }
     };
-    for (loc, node) in &st.table {
+    for (loc, node) in sorted_table(st) {
       if ! node.succs_def () {
         writeln!(&mut writer, "\"{:?}\" [shape=box];", loc).unwrap();
         continue;
@@ -2395,7 +3791,7 @@ mod wf {
       frame_num += 1;
     }
     let prefix = "debug_dcg::table: " ;
-    for (loc, node) in &st.table {
+    for (loc, node) in sorted_table(st) {
       println!("{} {:?} ==> {:?}", prefix, loc, node);
       if ! node.succs_def () { continue } ;
       for succ in node.succs () {
@@ -2403,21 +3799,4 @@ mod wf {
       }
     }      
   }
-
-  // XXX Does not catch errors in IC_Edit that I expected it would
-  // XXX Not sure if it works as I expected
-  pub fn check_stack_is_clean (st:&DCG) {
-    let stack = st.stack.clone() ;
-    for frame in stack.iter() {
-      let node = match st.table.get(&frame.loc) {
-        Some(x) => x,
-        None => { panic!("dangling: {:?}", &frame.loc) }
-      } ;
-      if ! node.succs_def () { return } ;
-      for succ in node.succs () {
-        let succ = super::get_succ(st, &frame.loc, succ.effect.clone(), &succ.loc) ;
-        assert!( succ.dirty ); // The edge is clean.
-      }
-    }
-  }
 }
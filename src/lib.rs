@@ -50,6 +50,8 @@ both predictable and scalable.
 #![feature(associated_consts)]
 #![feature(box_patterns)]
 #![feature(box_syntax)]
+#![feature(specialization)]
+#![allow(incomplete_features)]
 
 #![crate_name = "adapton"]
 #![crate_type = "lib"]
@@ -63,6 +65,7 @@ pub mod catalog ;
 pub mod parse_val;
 
 mod reflect;
+mod persist;
 
 
 mod adapton {
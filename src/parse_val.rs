@@ -38,12 +38,17 @@ enum Tok {
     Comma, 
 }
 
+/// Parse errors give a message; `parse_val` does not report them,
+/// but instead falls back to `Val::Unparsed`.
+type ParseResult<T> = Result<T, String>;
 
 /// Transform most(*) Rust data that derives `Debug` into a reflected
 /// `Val`.
 /// 
 /// This parsing logic handles user-defined `struct` and `enum`
-/// types, tuples and vectors.  It recognizes the `Debug` output of
+/// types, tuples, vectors, maps and sets (e.g., `HashMap`, `HashSet`
+/// and `BTreeMap`), and primitive data (integers, floats, booleans,
+/// characters and strings).  It recognizes the `Debug` output of
 /// these structures and parses them into trees of type
 /// `Val`. Importantly, it recognizes articulations in this `Debug`
 /// output and parses those into reflected locations (of type `Loc`)
@@ -51,29 +56,129 @@ enum Tok {
 /// reflected articulations (whose reflected locations are of type
 /// `Loc`) to reflected nodes that contain more reflected values.
 /// 
-/// (*) Note: Though this parsing logic handles vectors, tuples and
-/// user-defined data types, this parsing logic is probably
-/// incomplete for all of Rust's standard collections. (It does not
-/// yet handle `HashMap<_,_>` debug output, or anything else of this
-/// complexity as of yet).
-pub fn parse_val <V:Debug> (v:&V) -> Val {
+/// (*) Note: Custom `Debug` implementations may produce output that
+/// this parsing logic does not recognize.  In these cases, it does
+/// not fail; rather, it returns the `Debug` string as a
+/// `Val::Unparsed` value.
+pub fn parse_val <V:Debug+?Sized> (v:&V) -> Val {
     let s = format!("{:?}", v);
     //println!("reflect_val({:?})", v);
-    let toks = lex(s.into_bytes());
-    //println!("toks = {:?}", toks);
-    parse_toks(toks)
+    let parsed = match lex(s.chars().collect()) {
+        Ok(toks) => parse_toks(toks),
+        Err(e) => Err(e),
+    };
+    match parsed {
+        Ok(v) => v,
+        Err(_) => Val::Unparsed(s),
+    }
+}
+
+fn is_ident_start (c:char) -> bool { c.is_alphabetic() || c == '_' }
+fn is_ident_char  (c:char) -> bool { c.is_alphanumeric() || c == '_' }
+
+/// Lex the body of a string or character literal (after its opening
+/// quote), until the closing `quote`, interpreting the escape
+/// sequences of `Debug` output.
+fn lex_quoted (chars: &mut Vec<char>, quote:char) -> ParseResult<String> {
+    let mut s = String::new();
+    loop {
+        match chars.pop() {
+            None => return Err(format!("unterminated literal; expected {}", quote)),
+            Some(c) if c == quote => return Ok(s),
+            Some('\\') => {
+                match chars.pop() {
+                    Some('n')  => s.push('\n'),
+                    Some('t')  => s.push('\t'),
+                    Some('r')  => s.push('\r'),
+                    Some('0')  => s.push('\0'),
+                    Some('\\') => s.push('\\'),
+                    Some('\'') => s.push('\''),
+                    Some('"')  => s.push('"'),
+                    Some('u')  => {
+                        if chars.pop() != Some('{') {
+                            return Err(format!("expected {{ in unicode escape"))
+                        };
+                        let mut digs = String::new();
+                        loop {
+                            match chars.pop() {
+                                Some('}') => break,
+                                Some(c) if c.is_digit(16) => digs.push(c),
+                                c => return Err(format!("unexpected {:?} in unicode escape", c)),
+                            }
+                        };
+                        match u32::from_str_radix(digs.as_str(), 16).ok().and_then(::std::char::from_u32) {
+                            Some(c) => s.push(c),
+                            None => return Err(format!("invalid unicode escape: {}", digs)),
+                        }
+                    },
+                    c => return Err(format!("unexpected escape sequence: {:?}", c)),
+                }
+            },
+            Some(c) => s.push(c),
+        }
+    }
+}
+
+/// Lex a number whose first character (a digit, or `-`) is `c`.
+/// Integers become `Nat`s, or `Num`s when negative, or `BigInt`s when
+/// they do not fit in a `usize` (or `isize`); numbers with a
+/// fractional part or exponent become `Float`s.
+fn lex_num (chars: &mut Vec<char>, c:char) -> ParseResult<Const> {
+    let mut digs = vec![c];
+    let mut is_float = false;
+    loop {
+        match chars.pop() {
+            None    => break,
+            Some(c) => {
+                if c.is_digit(10) {
+                    digs.push(c);
+                } else if c == '.' && chars.last().map_or(false, |d| d.is_digit(10)) {
+                    is_float = true;
+                    digs.push(c);
+                } else if c == 'e' || c == 'E' {
+                    is_float = true;
+                    digs.push(c);
+                    match chars.pop() {
+                        Some(s) if s == '-' || s == '+' => digs.push(s),
+                        Some(s) => chars.push(s),
+                        None => (),
+                    }
+                } else {
+                    chars.push(c);
+                    break
+                }
+            }
+        }
+    };
+    let s : String = digs.into_iter().collect();
+    if is_float {
+        match s.parse::<f64>() {
+            Ok(_) => Ok(Const::Float(s)),
+            Err(_) => Err(format!("invalid float: {}", s)),
+        }
+    } else if c == '-' {
+        // The digits are valid, so only overflow fails.
+        match isize::from_str_radix(s.as_str(), 10) {
+            Ok(n) => Ok(Const::Num(n)),
+            Err(_) => Ok(Const::BigInt(s)),
+        }
+    } else {
+        match usize::from_str_radix(s.as_str(), 10) {
+            Ok(n) => Ok(Const::Nat(n)),
+            Err(_) => Ok(Const::BigInt(s)),
+        }
+    }
 }
 
 /// Tokenize the characters of input into lexical tokens of type `Tok`
-fn lex (mut chars: Vec<u8>) -> Vec<Tok> {
+fn lex (mut chars: Vec<char>) -> ParseResult<Vec<Tok>> {
     let mut toks = vec![];
     chars.reverse(); // TODO rewrite to avoid this
     loop {
         match chars.pop() {
-            None => return toks,
+            None => return Ok(toks),
             Some(c) => {
-                let c : char = c as char ;
-                if      c == ' ' { continue }
+                if      c.is_whitespace() { continue }
                 else if c == ':' { toks.push(Tok::Colon); continue }
                 else if c == ',' { toks.push(Tok::Comma); continue }
                 else if c == '{' { toks.push(Tok::Left (BalTok::Brace));   continue }
@@ -83,81 +188,83 @@ fn lex (mut chars: Vec<u8>) -> Vec<Tok> {
                 else if c == ']' { toks.push(Tok::Right(BalTok::Bracket)); continue }
                 else if c == ')' { toks.push(Tok::Right(BalTok::Paren));   continue }
                 else if c == '"' {
-                    let mut string_chars = vec![];
-                    loop {
-                        match chars.pop() {
-                            None => break,
-                            Some(c) => {
-                                let c : char = c as char ;
-                                if c == '"' { break } else { 
-                                    string_chars.push(c);
-                                    continue 
-                                }
-                            }
-                        }
+                    let s = lex_quoted(&mut chars, '"')?;
+                    toks.push(Tok::Const(Const::String(s)));
+                    continue
+                }
+                else if c == '\'' {
+                    let s = lex_quoted(&mut chars, '\'')?;
+                    let mut cs = s.chars();
+                    match (cs.next(), cs.next()) {
+                        (Some(c), None) => toks.push(Tok::Const(Const::Char(c))),
+                        _ => return Err(format!("invalid character literal: {:?}", s)),
                     };
-                    toks.push(Tok::Const(Const::String( 
-                        string_chars.into_iter().collect() 
-                    )));
                     continue
                 }
-                else if c == '-' || (c >= '0' && c <= '9') {
-                    let mut digs = vec![c];
-                    loop {
-                        match chars.pop() {
-                            None    => break,
-                            Some(c) => { 
-                                let c : char = c as char ;
-                                if c >= '0' && c <= '9' { 
-                                    digs.push(c); 
-                                    continue 
-                                } else { 
-                                    chars.push(c as u8); 
-                                    break 
-                                }
-                            }
-                        }
+                else if c == '-' && chars.last() == Some(&'i') {
+                    // Negative infinity, as printed by `Debug` for floats
+                    match (chars.pop(), chars.pop(), chars.pop()) {
+                        (Some('i'), Some('n'), Some('f')) =>
+                            toks.push(Tok::Const(Const::Float(String::from("-inf")))),
+                        _ => return Err(format!("expected -inf")),
                     };
-                    if c == '-' {
-                        let s : String = digs.into_iter().collect();
-                        toks.push(Tok::Const(Const::Num( 
-                            isize::from_str_radix(s.as_str(), 10).unwrap()
-                        )));              
-                    } else {
-                        let s : String = digs.into_iter().collect();
-                        toks.push(Tok::Const(Const::Nat( 
-                            usize::from_str_radix(s.as_str(), 10).unwrap()
-                        )));
-                    }
                     continue
                 }
-                else if (c >= 'a' && c <= 'z') ||
-                    (c >= 'A' && c <= 'Z') ||
-                    (c == '_') 
-                {
+                else if c == '-' || c.is_digit(10) {
+                    if c == '-' && ! chars.last().map_or(false, |d| d.is_digit(10)) {
+                        return Err(format!("expected digits after -"))
+                    };
+                    let n = lex_num(&mut chars, c)?;
+                    toks.push(Tok::Const(n));
+                    continue
+                }
+                else if is_ident_start(c) {
                     let mut ident = vec![c];
                     loop {
                         match chars.pop() {
                             None    => break,
                             Some(c) => { 
-                                let c : char = c as char ;
-                                if (c >= 'a' && c <= 'z') ||
-                                    (c >= 'A' && c <= 'Z') ||
-                                    (c >= '0' && c <= '9') ||
-                                    (c == '_')  
-                                {
+                                if is_ident_char(c) {
                                     ident.push(c); 
                                     continue 
+                                } else if c == '<' {
+                                    // Type arguments, as printed by `Debug` for
+                                    // `PhantomData<T>`; they become part of the identifier.
+                                    ident.push(c);
+                                    let mut depth = 1;
+                                    while depth > 0 {
+                                        match chars.pop() {
+                                            None => return Err(format!("unbalanced type arguments")),
+                                            Some(c) => {
+                                                if c == '<' { depth += 1 }
+                                                else if c == '>' { depth -= 1 };
+                                                ident.push(c)
+                                            }
+                                        }
+                                    };
+                                    break 
                                 } else { 
-                                    chars.push(c as u8); 
+                                    chars.push(c);
                                     break 
                                 }
                             }
                         }
                     };
-                    toks.push(Tok::Ident( ident.into_iter().collect() ));
+                    let ident : String = ident.into_iter().collect();
+                    if ident == "true" {
+                        toks.push(Tok::Const(Const::Bool(true)))
+                    } else if ident == "false" {
+                        toks.push(Tok::Const(Const::Bool(false)))
+                    } else if ident == "inf" || ident == "NaN" {
+                        toks.push(Tok::Const(Const::Float(ident)))
+                    } else {
+                        toks.push(Tok::Ident( ident ))
+                    };
                     continue           
                 }
+                else {
+                    return Err(format!("unexpected character: {:?}", c))
+                }
             }
         }
     } ;
@@ -165,23 +272,25 @@ fn lex (mut chars: Vec<u8>) -> Vec<Tok> {
 
 /// Parse a sequence of fields (appending to `fields`) until right
 /// balanced token `bal`.  Return fields and remaining tokens.
-fn parse_fields (mut toks:Vec<Tok>, mut fields:Vec<(Name, Val)>, bal:Tok) -> (Vec<(Name, Val)>, Vec<Tok>) {
-    match toks.pop() {
-        None => panic!("parse_vals: expected more vals, or end of sequence; but no more tokens"),
-        Some(t) => {
-            if t == bal { (fields, toks) } 
-            else if t == Tok::Comma { 
-                return parse_fields(toks, fields, bal)
-            } else {
-                match t {
-                    Tok::Ident(i) => {
-                        let toks = expect_tok(toks, Tok::Colon);
-                        let (v, toks) = parse_val_rec(toks);
-                        fields.push((name_of_string(i), v));
-                        return parse_fields(toks, fields, bal)
-                    }
-                    t => {
-                        panic!("parse_fields: expected identifier, but found {:?}", t)
+fn parse_fields (mut toks:Vec<Tok>, mut fields:Vec<(Name, Val)>, bal:Tok) -> ParseResult<(Vec<(Name, Val)>, Vec<Tok>)> {
+    loop {
+        match toks.pop() {
+            None => return Err(format!("parse_fields: expected more fields, or end of sequence; but no more tokens")),
+            Some(t) => {
+                if t == bal { return Ok((fields, toks)) }
+                else if t == Tok::Comma {
+                    continue
+                } else {
+                    match t {
+                        Tok::Ident(i) => {
+                            let rest = expect_tok(toks, Tok::Colon)?;
+                            let (v, rest) = parse_val_rec(rest)?;
+                            fields.push((name_of_string(i), v));
+                            toks = rest;
+                        }
+                        t => {
+                            return Err(format!("parse_fields: expected identifier, but found {:?}", t))
+                        }
                     }
                 }
             }
@@ -191,98 +300,84 @@ fn parse_fields (mut toks:Vec<Tok>, mut fields:Vec<(Name, Val)>, bal:Tok) -> (Ve
 
 /// Parse a sequence of values (appending to `vals`) until right
 /// balanced token `bal`.  Return fields and remaining tokens.
-fn parse_vals (mut toks:Vec<Tok>, mut vals:Vec<Val>, bal:Tok) -> (Vec<Val>, Vec<Tok>) {
-    match toks.pop() {
-        None => panic!("parse_vals: expected more vals, or end of sequence; but no more tokens"),
-        Some(t) => {
-            if t == bal { (vals, toks) } 
-            else if t == Tok::Comma { 
-                return parse_vals(toks, vals, bal)
-            } 
-            else {
-                toks.push(t);
-                let (v, toks) = parse_val_rec(toks);
-                vals.push(v);
-                return parse_vals(toks, vals, bal)
+fn parse_vals (mut toks:Vec<Tok>, mut vals:Vec<Val>, bal:Tok) -> ParseResult<(Vec<Val>, Vec<Tok>)> {
+    loop {
+        match toks.pop() {
+            None => return Err(format!("parse_vals: expected more vals, or end of sequence; but no more tokens")),
+            Some(t) => {
+                if t == bal { return Ok((vals, toks)) }
+                else if t == Tok::Comma {
+                    continue
+                }
+                else {
+                    toks.push(t);
+                    let (v, rest) = parse_val_rec(toks)?;
+                    vals.push(v);
+                    toks = rest;
+                }
             }
         }
     }
 }
 
-/// Expect next token to be `tok` and panic otherwise.
-fn expect_tok (mut toks: Vec<Tok>, tok:Tok) -> Vec<Tok> {
+/// Parse the entries of a map (appending to `entries`), after its
+/// first key, until the right brace.  Return entries and remaining
+/// tokens.
+fn parse_entries (mut toks:Vec<Tok>, mut entries:Vec<(Val, Val)>, key:Val) -> ParseResult<(Vec<(Val, Val)>, Vec<Tok>)> {
+    let mut key = key;
+    loop {
+        let rest = expect_tok(toks, Tok::Colon)?;
+        let (v, mut rest) = parse_val_rec(rest)?;
+        entries.push((key, v));
+        match rest.pop() {
+            Some(Tok::Right(BalTok::Brace)) => return Ok((entries, rest)),
+            Some(Tok::Comma) => {
+                match rest.pop() {
+                    Some(Tok::Right(BalTok::Brace)) => return Ok((entries, rest)),
+                    Some(t) => {
+                        rest.push(t);
+                        let (k, rest) = parse_val_rec(rest)?;
+                        key = k;
+                        toks = rest;
+                    },
+                    None => return Err(format!("parse_entries: expected more entries; but no more tokens")),
+                }
+            },
+            t => return Err(format!("parse_entries: expected comma or right brace, but found {:?}", t)),
+        }
+    }
+}
+
+/// Expect next token to be `tok`, and fail otherwise.
+fn expect_tok (mut toks: Vec<Tok>, tok:Tok) -> ParseResult<Vec<Tok>> {
     match toks.pop() {
-        None => panic!("expected token `{:?}`, but, no more tokens", tok),
+        None => Err(format!("expected token `{:?}`, but, no more tokens", tok)),
         Some(t) => {
-            if t == tok { toks } 
-            else { panic!("expected token `{:?}`, but instead found token `{:?}`", tok, t) }
+            if t == tok { Ok(toks) }
+            else { Err(format!("expected token `{:?}`, but instead found token `{:?}`", tok, t)) }
         }
     }
 }
 
-fn parse_toks(mut toks:Vec<Tok>) -> Val {
+fn parse_toks(mut toks:Vec<Tok>) -> ParseResult<Val> {
     toks.reverse();
-    let (v, toks) = parse_val_rec(toks);
-    assert!(toks.len() == 0);
-    v
+    let (v, toks) = parse_val_rec(toks)?;
+    if toks.len() == 0 { Ok(v) }
+    else { Err(format!("expected end of input, but found {:?}", toks.last())) }
 }
 
-fn path_of_val ( p:&Val ) -> Path {
+fn path_of_val ( p:&Val ) -> Option<Path> {
     match *p {
-        Val::Vec( ref vs ) => vs.iter().map( name_of_val ).collect(),
-        _ => panic!("expected a vector of values representing names"),
-    }
-}
-
-fn name_of_val ( n:&Val ) -> Name {
-    use engine::*;
-
-    match *n {
-        Val::Constr( ref cons_name, ref cons_args ) => {
-            if *cons_name == name_of_str("Unit") {
-                name_unit()
-            }
-            else if *cons_name == name_of_str("Hash64") {
-                name_of_hash64( 0 ) // TODO/XXX
-            }
-            else if *cons_name == name_of_str("String") {
-                name_of_string( match cons_args[0] {
-                    Val::Const( Const::String( ref s ) ) => s.clone(),
-                    _ => panic!("expected a String"),
-                })
-            }
-            else if *cons_name == name_of_str("Usize") {
-                name_of_usize( match cons_args[0] {
-                    Val::Const( Const::Nat( ref n ) ) => n.clone(),
-                    _ => panic!("expected a Nat"),
-                })
-            }
-            else if *cons_name == name_of_str("Isize") {
-                panic!("")
-            }
-            else if *cons_name == name_of_str("Pair") {
-                let n1 = name_of_val( & cons_args[0] );
-                let n2 = name_of_val( & cons_args[1] );
-                name_pair(n1, n2)
-            }
-            else if *cons_name == name_of_str("ForkL") {
-                let n = name_of_val( & cons_args[0] );
-                name_fork(n).0
-            }
-            else if *cons_name == name_of_str("ForkR") {
-                let n = name_of_val( & cons_args[0] );
-                name_fork(n).1
-            }
-            else {
-                unreachable!()
-            }        
-        },
-        Val::Name(ref n) => n.clone(),
-        _ => panic!("expected a constructor for a NameSym")
+        Val::Vec( ref vs ) => vs.iter().map( |v| name_option_of_val( v, true ) ).collect(),
+        _ => None,
     }
 }
 
-fn name_option_of_val ( n:&Val ) -> Option<Name> {
+/// The name that `n` prints, if any.  Within a location (`in_loc`),
+/// the constructors of `U64` and `Bytes` symbols are names too;
+/// elsewhere, they are more likely those of user types, and are left
+/// as constructors.
+fn name_option_of_val ( n:&Val, in_loc:bool ) -> Option<Name> {
     use engine::*;
     
     match *n {
@@ -291,7 +386,11 @@ fn name_option_of_val ( n:&Val ) -> Option<Name> {
                 Some(name_unit())
             }
             else if *cons_name == name_of_str("Hash64") {
-                Some(name_of_hash64( 0 )) // XXX \ TODO -- We are actually missing the hash here!
+                if cons_args.len() < 1 { None } else {
+                    match cons_args[0] {
+                        Val::Const( ref c ) => u64_of_const( c ).map( name_of_hash64 ),
+                        _ => None,
+                    }}
             }
             else if *cons_name == name_of_str("String") {
                 if cons_args.len() < 1 { None } else {
@@ -309,21 +408,45 @@ fn name_option_of_val ( n:&Val ) -> Option<Name> {
                     }}
             }
             else if *cons_name == name_of_str("Isize") {
-                panic!("TODO")
+                if cons_args.len() < 1 { None } else {
+                    match cons_args[0] {
+                        Val::Const( Const::Num( ref n ) ) => Some( name_of_isize( n.clone() ) ),
+                        Val::Const( Const::Nat( ref n ) ) => Some( name_of_isize( n.clone() as isize ) ),
+                        _ => None,
+                    }}
+            }
+            else if in_loc && *cons_name == name_of_str("U64") {
+                if cons_args.len() < 1 { None } else {
+                    match cons_args[0] {
+                        Val::Const( ref c ) => u64_of_const( c ).map( name_of_u64 ),
+                        _ => None,
+                    }}
+            }
+            else if in_loc && *cons_name == name_of_str("Bytes") {
+                if cons_args.len() < 1 { None } else {
+                    match cons_args[0] {
+                        Val::Vec( ref vs ) => {
+                            let bytes : Option<Vec<u8>> = vs.iter().map(|v| match *v {
+                                Val::Const( Const::Nat( ref n ) ) if *n < 256 => Some(*n as u8),
+                                _ => None,
+                            }).collect();
+                            bytes.map(|b| name_of_bytes(&b))
+                        },
+                        _ => None,
+                    }}
             }
             else if *cons_name == name_of_str("Pair") {          
                 if cons_args.len() < 2 { None } else {
-                    let n1 = name_option_of_val( & cons_args[0] );
-                    let n2 = name_option_of_val( & cons_args[1] );
-                    if cons_args.len() < 2 { None } else {
-                        match (n1,n2) {
-                            (Some(n1),Some(n2)) => Some(name_pair(n1, n2)),
-                            (_, _) => None,
-                        }}}
+                    let n1 = name_option_of_val( & cons_args[0], in_loc );
+                    let n2 = name_option_of_val( & cons_args[1], in_loc );
+                    match (n1,n2) {
+                        (Some(n1),Some(n2)) => Some(name_pair(n1, n2)),
+                        (_, _) => None,
+                    }}
             }
             else if *cons_name == name_of_str("ForkL") {
                 if cons_args.len() < 1 { None } else {
-                    let n = name_option_of_val( & cons_args[0] );
+                    let n = name_option_of_val( & cons_args[0], in_loc );
                     match n {
                         None => None,
                         Some(n) => Some(name_fork(n).0)
@@ -331,7 +454,7 @@ fn name_option_of_val ( n:&Val ) -> Option<Name> {
             }
             else if *cons_name == name_of_str("ForkR") {
                 if cons_args.len() < 1 { None } else {
-                    let n = name_option_of_val( & cons_args[0] );
+                    let n = name_option_of_val( & cons_args[0], in_loc );
                     match n {
                         None => None,
                         Some(n) => Some(name_fork(n).1)
@@ -344,6 +467,26 @@ fn name_option_of_val ( n:&Val ) -> Option<Name> {
     }
 }
 
+/// The `u64` of a natural number constant, if it fits.
+fn u64_of_const ( c:&Const ) -> Option<u64> {
+    match *c {
+        Const::Nat( n ) => Some( n as u64 ),
+        Const::BigInt( ref s ) => s.parse::<u64>().ok(),
+        _ => None,
+    }
+}
+
+/// The name of the `id` of a location: a structural id is printed as
+/// its hash (a natural number), and a nominal id as its name.
+fn id_option_of_val ( id:&Val ) -> Option<Name> {
+    use engine::*;
+
+    match *id {
+        Val::Const( ref c ) => u64_of_const( c ).map( name_of_hash64 ),
+        _ => name_option_of_val( id, true ),
+    }
+}
+
 
 /// Attempts to parse a reflected value into an `Art` value case,
 /// which consists of parsing a location represented as a `Val`
@@ -365,14 +508,12 @@ fn parse_art_val ( i:&String, fields:&Vec<(Name, Val)> ) -> Option<Val> {
                             && ws[0].0 == name_of_str("path") 
                             && ws[1].0 == name_of_str("id")
                         {
-                            // Now we are confident that the rest ought to parse.
-                            // Any further parse errors are panics.
-                            let path = path_of_val( & ws[0].1 );
-                            let name = name_of_val( & ws[1].1 );
-                            Some( Val::Art(Loc{path:path, name:name}, ArtContent::Unknown) )
+                            match (path_of_val( & ws[0].1 ), id_option_of_val( & ws[1].1 )) {
+                                (Some(path), Some(name)) =>
+                                    Some( Val::Art(Loc{path:path, name:name}, ArtContent::Unknown) ),
+                                _ => None,
+                            }
                         } 
-
-
                         else { 
                             None 
                         },
@@ -382,25 +523,50 @@ fn parse_art_val ( i:&String, fields:&Vec<(Name, Val)> ) -> Option<Val> {
         }} else { None }           
 }
 
-/// Parse a value from the tokens `toks` and return it.  Panic if the next tokens do not parse into value.
-fn parse_val_rec (mut toks:Vec<Tok>) -> (Val, Vec<Tok>) {
+/// Parse a value from the tokens `toks` and return it.  Fail if the next tokens do not parse into value.
+fn parse_val_rec (mut toks:Vec<Tok>) -> ParseResult<(Val, Vec<Tok>)> {
     //println!("{:?}", toks);
     let (v, toks) = match toks.pop() {
-        None => panic!("expected value; but, no more tokens"),
-        Some(Tok::Right(r)) => panic!("expected value, but found {:?} instead", Tok::Right(r)),
-        Some(Tok::Comma) => panic!("expected value, but found Comma instead"),
-        Some(Tok::Colon) => panic!("expected value, but found Colon instead"),
+        None => return Err(format!("expected value; but, no more tokens")),
+        Some(Tok::Right(r)) => return Err(format!("expected value, but found {:?} instead", Tok::Right(r))),
+        Some(Tok::Comma) => return Err(format!("expected value, but found Comma instead")),
+        Some(Tok::Colon) => return Err(format!("expected value, but found Colon instead")),
         Some(Tok::Left(BalTok::Bracket)) => {
             // Parse a vector: Begins with '[', then a list of comma-separated values, then ']'.
-            let (vs, toks) = parse_vals(toks, vec![], Tok::Right(BalTok::Bracket));
+            let (vs, toks) = parse_vals(toks, vec![], Tok::Right(BalTok::Bracket))?;
             (Val::Vec(vs), toks)
         },
         Some(Tok::Left(BalTok::Paren)) => {
             // Parse a tuple: Begins with '(', then a list of comma-separated values, then ')'.
-            let (vs, toks) = parse_vals(toks, vec![], Tok::Right(BalTok::Paren));
+            let (vs, toks) = parse_vals(toks, vec![], Tok::Right(BalTok::Paren))?;
             (Val::Tuple(vs), toks)
         },
-        Some(Tok::Left(l)) => panic!("expected value, but found {:?} instead", Tok::Left(l)),     
+        Some(Tok::Left(BalTok::Brace)) => {
+            // Parse a map or a set: Begins with '{', then a list of
+            // comma-separated entries (`key: value`) or values, then '}'.
+            match toks.pop() {
+                None => return Err(format!("expected map or set; but, no more tokens")),
+                // The empty map and the empty set look the same; we choose the map.
+                Some(Tok::Right(BalTok::Brace)) => (Val::Map(vec![]), toks),
+                Some(t) => {
+                    toks.push(t);
+                    let (k, mut toks) = parse_val_rec(toks)?;
+                    match toks.pop() {
+                        Some(Tok::Colon) => {
+                            toks.push(Tok::Colon);
+                            let (es, toks) = parse_entries(toks, vec![], k)?;
+                            (Val::Map(es), toks)
+                        },
+                        Some(t) => {
+                            toks.push(t);
+                            let (vs, toks) = parse_vals(toks, vec![k], Tok::Right(BalTok::Brace))?;
+                            (Val::Set(vs), toks)
+                        },
+                        None => return Err(format!("expected map or set; but, no more tokens")),
+                    }
+                }
+            }
+        },
         Some(Tok::Ident(i)) => {
             match toks.pop() {
                 None => {
@@ -409,7 +575,7 @@ fn parse_val_rec (mut toks:Vec<Tok>) -> (Val, Vec<Tok>) {
                 }
                 Some(Tok::Left(BalTok::Brace)) => {
                     //println!("parsing struct: {:?}", i);
-                    let (fields, toks) = parse_fields(toks, vec![], Tok::Right(BalTok::Brace));
+                    let (fields, toks) = parse_fields(toks, vec![], Tok::Right(BalTok::Brace))?;
                     let art_op = parse_art_val(&i, &fields);
                     let v = match art_op {
                         Some(a) => a,
@@ -419,27 +585,230 @@ fn parse_val_rec (mut toks:Vec<Tok>) -> (Val, Vec<Tok>) {
                 }
                 Some(Tok::Left(BalTok::Paren)) => {
                     //println!("parsing constructor: {:?}", i);
-                    let (vs, toks) = parse_vals(toks, vec![], Tok::Right(BalTok::Paren));
+                    let (vs, toks) = parse_vals(toks, vec![], Tok::Right(BalTok::Paren))?;
                     let v = Val::Constr(name_of_string(i), vs);
-                    match name_option_of_val(&v) {
+                    match name_option_of_val(&v, false) {
                         Some(n) => (Val::Name(n), toks),
                         None => (v, toks)
                     }
                 },
-                Some(Tok::Comma) => {
-                    toks.push(Tok::Comma);
-                    (Val::Constr(name_of_string(i), vec![]), toks)
-                },
-                Some(Tok::Right(baltok)) => {
-                    toks.push(Tok::Right(baltok));
+                Some(t @ Tok::Comma) |
+                Some(t @ Tok::Colon) |
+                Some(t @ Tok::Right(_)) => {
+                    // Constructors with no arguments, and unit structs,
+                    // followed by more of an enclosing value
+                    toks.push(t);
                     (Val::Constr(name_of_string(i), vec![]), toks)
                 },
                 Some(t) => {
-                    panic!("expected left balanced token, or comma, but instead found token {:?}", t)
+                    return Err(format!("expected left balanced token, or comma, but instead found token {:?}", t))
                 }}},
-        Some(Tok::Const(Const::Nat(n)))    => (Val::Const(Const::Nat(n)), toks),
-        Some(Tok::Const(Const::Num(n)))    => (Val::Const(Const::Num(n)), toks),
-        Some(Tok::Const(Const::String(s))) => (Val::Const(Const::String(s)), toks)
+        Some(Tok::Const(c)) => (Val::Const(c), toks),
+    };
+    Ok((v,toks))
+}
+
+/// Parse a name from the concise form that `reflect::write_name`
+/// writes (rather than from its `Debug` form, as `parse_val` does).
+///
+/// Fails on input that `write_name` does not write, including input
+/// whose reading is ambiguous: a bare `l` or `r` that is not a fork
+/// suffix, and an unquoted string that ends with, or contains a run
+/// of, underscores (which separate the names of paths).
+pub fn parse_name (s:&str) -> Result<Name, String> {
+    let mut chars = rev_chars(s);
+    let n = parse_name_chain(&mut chars).and_then(|n| {
+        expect_end(&chars)?; Ok(n)
+    });
+    n.map_err(|e| format!("parse_name: {}, in {:?}", e, s))
+}
+
+/// Parse a path from the concise form that `reflect::write_path`
+/// writes; see `parse_name`.
+pub fn parse_path (s:&str) -> Result<Path, String> {
+    let mut chars = rev_chars(s);
+    let p = parse_path_chars(&mut chars).and_then(|p| {
+        expect_end(&chars)?; Ok(p)
+    });
+    p.map_err(|e| format!("parse_path: {}, in {:?}", e, s))
+}
+
+/// Parse a location from the concise form that `reflect::write_loc`
+/// writes; see `parse_name`.
+pub fn parse_loc (s:&str) -> Result<Loc, String> {
+    let mut chars = rev_chars(s);
+    let l = parse_path_chars(&mut chars).and_then(|path| {
+        match chars.pop() {
+            Some('_') => (),
+            _ => return Err(format!("expected `___` before the name of the location")),
+        };
+        let name = parse_name_chain(&mut chars)?;
+        expect_end(&chars)?;
+        Ok(Loc{ path:path, name:name })
+    });
+    l.map_err(|e| format!("parse_loc: {}, in {:?}", e, s))
+}
+
+fn rev_chars (s:&str) -> Vec<char> {
+    let mut chars : Vec<char> = s.chars().collect();
+    chars.reverse();
+    chars
+}
+
+fn expect_end (chars:&Vec<char>) -> ParseResult<()> {
+    match chars.last() {
+        None => Ok(()),
+        Some(c) => Err(format!("expected end of input, but found {:?}", c)),
+    }
+}
+
+/// True iff the next characters are a separator (`__`) of the names
+/// of a path.
+fn at_separator (chars:&Vec<char>) -> bool {
+    chars.len() >= 2 && chars[chars.len() - 1] == '_' && chars[chars.len() - 2] == '_'
+}
+
+/// Parse the names of a path, each followed by a separator (`__`),
+/// after a leading separator.  Stops before an underscore that
+/// follows a separator, which begins the name of a location.
+fn parse_path_chars (chars:&mut Vec<char>) -> ParseResult<Path> {
+    if !at_separator(chars) {
+        return Err(format!("expected `__` at the start of the path"))
     };
-    (v,toks)
+    chars.pop(); chars.pop();
+    let mut path = vec![];
+    loop {
+        match chars.last() {
+            None => return Ok(path),
+            Some(&'_') => {
+                if chars.len() >= 2 && chars[chars.len() - 2] == '_' {
+                    return Err(format!("ambiguous run of more than three underscores"))
+                };
+                return Ok(path)
+            },
+            Some(_) => {
+                path.push(parse_name_chain(chars)?);
+                if !at_separator(chars) {
+                    return Err(format!("expected `__` after name {:?} of the path", path.last().unwrap()))
+                };
+                chars.pop(); chars.pop();
+            }
+        }
+    }
+}
+
+/// Parse a name: an atom, followed by pair components and fork
+/// suffixes (each after a `-`), which associate to the left.
+fn parse_name_chain (chars:&mut Vec<char>) -> ParseResult<Name> {
+    use engine::*;
+    let mut n = parse_name_atom(chars)?;
+    loop {
+        match chars.last() {
+            Some(&'-') => {
+                chars.pop();
+                match fork_suffix(chars) {
+                    Some('l') => { chars.pop(); n = name_fork(n).0 },
+                    Some(_)   => { chars.pop(); n = name_fork(n).1 },
+                    None      => { let m = parse_name_atom(chars)?; n = name_pair(n, m) },
+                }
+            },
+            _ => return Ok(n),
+        }
+    }
+}
+
+/// Returns `l` or `r` when the next character is a fork suffix, which
+/// is followed by the end of the name.
+fn fork_suffix (chars:&Vec<char>) -> Option<char> {
+    let len = chars.len();
+    match chars.last() {
+        Some(&c) if c == 'l' || c == 'r' => {
+            let ends = len == 1 || chars[len - 2] == '-' || chars[len - 2] == ')' ||
+                (len >= 3 && chars[len - 2] == '_' && chars[len - 3] == '_');
+            if ends { Some(c) } else { None }
+        },
+        _ => None,
+    }
+}
+
+/// Pop the (possibly empty) run of characters that satisfy `pred`.
+fn lex_word<F:Fn(char) -> bool> (chars:&mut Vec<char>, mut word:String, pred:F) -> String {
+    loop {
+        match chars.last() {
+            Some(&c) if pred(c) => { chars.pop(); word.push(c) },
+            _ => return word,
+        }
+    }
+}
+
+fn parse_name_atom (chars:&mut Vec<char>) -> ParseResult<Name> {
+    use engine::*;
+    match chars.pop() {
+        None => Err(format!("expected a name, but found the end of input")),
+        Some('▲') => Ok(name_unit()),
+        Some('(') => {
+            let n = parse_name_chain(chars)?;
+            match chars.pop() {
+                Some(')') => Ok(n),
+                c => Err(format!("expected `)`, but found {:?}", c)),
+            }
+        },
+        Some('"') => Ok(name_of_string(lex_quoted(chars, '"')?)),
+        Some('#') => {
+            let digs = lex_word(chars, String::new(), |c| c.is_digit(16));
+            match u64::from_str_radix(digs.as_str(), 16) {
+                Ok(h) => Ok(name_of_hash64(h)),
+                Err(_) => Err(format!("invalid hash: #{}", digs)),
+            }
+        },
+        Some(c) if c == '+' || c == '-' => {
+            let digs = lex_word(chars, String::new(), |c| c.is_digit(10));
+            match isize::from_str_radix(format!("{}{}", c, digs).as_str(), 10) {
+                Ok(i) => Ok(name_of_isize(i)),
+                Err(_) => Err(format!("invalid isize: {}{}", c, digs)),
+            }
+        },
+        Some(c) if c.is_digit(10) => {
+            let word = lex_word(chars, c.to_string(), |c| c.is_ascii_alphanumeric());
+            if word.starts_with("0x") {
+                let hex = &word[2..];
+                let bytes : Option<Vec<u8>> = if hex.len() % 2 != 0 { None } else {
+                    (0..hex.len() / 2).map(|i| u8::from_str_radix(&hex[2*i..2*i+2], 16).ok()).collect()
+                };
+                match bytes {
+                    Some(b) => Ok(name_of_bytes(&b)),
+                    None => Err(format!("invalid bytes: {}", word)),
+                }
+            } else if word.ends_with("u64") {
+                match u64::from_str_radix(&word[..word.len() - 3], 10) {
+                    Ok(u) => Ok(name_of_u64(u)),
+                    Err(_) => Err(format!("invalid u64: {}", word)),
+                }
+            } else {
+                match usize::from_str_radix(word.as_str(), 10) {
+                    Ok(u) => Ok(name_of_usize(u)),
+                    Err(_) => Err(format!("invalid usize: {}", word)),
+                }
+            }
+        },
+        Some(c) if c.is_ascii_alphabetic() => {
+            let mut word = c.to_string();
+            loop {
+                // Stop before a separator (`__`).
+                if at_separator(chars) { break };
+                match chars.last() {
+                    Some(&c) if c.is_ascii_alphanumeric() || c == '_' => { chars.pop(); word.push(c) },
+                    _ => break,
+                }
+            };
+            if word == "l" || word == "r" {
+                Err(format!("ambiguous bare {:?}, which is not a fork suffix; quote it as a string", word))
+            } else if word.ends_with('_') {
+                Err(format!("ambiguous bare {:?}, which ends with an underscore; quote it as a string", word))
+            } else {
+                Ok(name_of_string(word))
+            }
+        },
+        Some(c) => Err(format!("unexpected {:?} in name", c)),
+    }
 }
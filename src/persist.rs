@@ -0,0 +1,291 @@
+//! Persistent collections, for forking the DCG cheaply.
+//!
+//! A clone shares all of its structure with the original, in O(1)
+//! time.  A write copies only the path of the trie leading to the
+//! written element (O(log n) time), so a fork and its original never
+//! observe each other's writes.
+
+use std::borrow::Borrow;
+use std::fmt;
+use std::hash::{Hash,Hasher,BuildHasher};
+use std::marker::PhantomData;
+use std::mem::replace;
+use std::ops::Index;
+use std::rc::Rc;
+
+const BITS  : u32   = 5;
+const WIDTH : usize = 1 << BITS;
+const MASK  : u64   = (WIDTH - 1) as u64;
+
+/// The most entries that a map leaf holds before it is split; leaves
+/// at the bottom of the trie (where the hash bits run out) are never
+/// split, and hold every entry whose hash collides.
+const LEAF_MAX : usize = 8;
+
+#[derive(Clone)]
+enum VecNode<T> {
+  Branch(Vec<Rc<VecNode<T>>>),
+  Leaf(Vec<T>),
+}
+
+/// A persistent vector: a trie of `WIDTH`-way branches, whose leaves
+/// hold the elements in order.
+pub struct PVec<T> {
+  len   : usize,
+  shift : u32, // shift of the index bits that select a child of the root; zero when the root is a leaf
+  root  : Rc<VecNode<T>>,
+}
+
+impl<T> PVec<T> {
+  pub fn new() -> Self {
+    PVec{ len:0, shift:0, root:Rc::new(VecNode::Leaf(Vec::new())) }
+  }
+
+  pub fn len(&self) -> usize { self.len }
+
+  pub fn get(&self, i:usize) -> Option<&T> {
+    if i >= self.len { return None } ;
+    let mut node = &*self.root;
+    let mut shift = self.shift;
+    loop {
+      match *node {
+        VecNode::Branch(ref children) => {
+          node = &*children[(i >> shift) & MASK as usize];
+          shift -= BITS
+        },
+        VecNode::Leaf(ref elms) => return Some(&elms[i & MASK as usize]),
+      }
+    }
+  }
+
+  pub fn iter<'r>(&'r self) -> impl Iterator<Item=&'r T> + 'r {
+    (0..self.len).map(move |i| &self[i])
+  }
+}
+
+impl<T:Clone> PVec<T> {
+  /// The element at `i`, for writing; copies the path to it first, if
+  /// this path is shared with a clone.
+  pub fn get_mut(&mut self, i:usize) -> Option<&mut T> {
+    if i >= self.len { return None } ;
+    let mut node = Rc::make_mut(&mut self.root);
+    let mut shift = self.shift;
+    loop {
+      match *{node} {
+        VecNode::Branch(ref mut children) => {
+          node = Rc::make_mut(&mut children[(i >> shift) & MASK as usize]);
+          shift -= BITS
+        },
+        VecNode::Leaf(ref mut elms) => return Some(&mut elms[i & MASK as usize]),
+      }
+    }
+  }
+
+  pub fn push(&mut self, elm:T) {
+    if self.len == WIDTH << self.shift {
+      // The trie is full; grow a new root above it.
+      let root = replace(&mut self.root, Rc::new(VecNode::Branch(Vec::new())));
+      Rc::make_mut(&mut self.root).push_child(root);
+      self.shift += BITS
+    } ;
+    let i = self.len;
+    let mut node = Rc::make_mut(&mut self.root);
+    let mut shift = self.shift;
+    loop {
+      match *{node} {
+        VecNode::Branch(ref mut children) => {
+          let idx = (i >> shift) & MASK as usize;
+          if idx == children.len() {
+            let child = if shift == BITS { VecNode::Leaf(Vec::new()) } else { VecNode::Branch(Vec::new()) };
+            children.push(Rc::new(child))
+          } ;
+          node = Rc::make_mut(&mut children[idx]);
+          shift -= BITS
+        },
+        VecNode::Leaf(ref mut elms) => { elms.push(elm); break },
+      }
+    } ;
+    self.len += 1
+  }
+}
+
+impl<T> VecNode<T> {
+  fn push_child(&mut self, child:Rc<VecNode<T>>) {
+    match *self {
+      VecNode::Branch(ref mut children) => children.push(child),
+      VecNode::Leaf(_) => unreachable!(),
+    }
+  }
+}
+
+impl<T> Clone for PVec<T> {
+  fn clone(&self) -> Self {
+    PVec{ len:self.len, shift:self.shift, root:self.root.clone() }
+  }
+}
+
+impl<T> Index<usize> for PVec<T> {
+  type Output = T;
+  fn index(&self, i:usize) -> &T {
+    match self.get(i) {
+      Some(elm) => elm,
+      None => panic!("PVec index out of bounds: the len is {} but the index is {}", self.len, i),
+    }
+  }
+}
+
+impl<T:fmt::Debug> fmt::Debug for PVec<T> {
+  fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+    f.debug_list().entries(self.iter()).finish()
+  }
+}
+
+#[derive(Clone)]
+enum MapNode<K,V> {
+  Branch(Vec<Option<Rc<MapNode<K,V>>>>), // `WIDTH` children, selected by the bits of the hash
+  Leaf(Vec<(u64,K,V)>),
+}
+
+/// A persistent hash map: a trie of `WIDTH`-way branches, selected by
+/// successive bits of each key's hash, whose leaves hold the entries.
+pub struct PMap<K,V,S> {
+  len  : usize,
+  root : Rc<MapNode<K,V>>,
+  hasher : PhantomData<S>,
+}
+
+impl<K:Hash+Eq,V,S:BuildHasher+Default> PMap<K,V,S> {
+  pub fn new() -> Self {
+    PMap{ len:0, root:Rc::new(MapNode::Leaf(Vec::new())), hasher:PhantomData }
+  }
+
+  fn hash<Q:?Sized+Hash>(k:&Q) -> u64 {
+    let mut hasher = S::default().build_hasher();
+    k.hash(&mut hasher);
+    hasher.finish()
+  }
+
+  pub fn get<Q:?Sized+Hash+Eq>(&self, k:&Q) -> Option<&V> where K:Borrow<Q> {
+    let hash = Self::hash(k);
+    let mut node = &*self.root;
+    let mut shift = 0;
+    loop {
+      match *node {
+        MapNode::Branch(ref children) => {
+          match children[((hash >> shift) & MASK) as usize] {
+            None => return None,
+            Some(ref child) => { node = &**child; shift += BITS },
+          }
+        },
+        MapNode::Leaf(ref entries) => {
+          return entries.iter()
+            .find(|&&(h, ref k2, _)| h == hash && k2.borrow() == k)
+            .map(|&(_, _, ref v)| v)
+        },
+      }
+    }
+  }
+
+  /// The entries of the map, in no particular order.
+  pub fn entries(&self) -> Vec<(&K,&V)> {
+    fn walk<'r,K,V>(node:&'r MapNode<K,V>, out:&mut Vec<(&'r K,&'r V)>) {
+      match *node {
+        MapNode::Branch(ref children) => for child in children.iter() {
+          if let Some(ref child) = *child { walk(child, out) }
+        },
+        MapNode::Leaf(ref entries) => out.extend(entries.iter().map(|&(_, ref k, ref v)| (k, v))),
+      }
+    }
+    let mut out = Vec::with_capacity(self.len);
+    walk(&self.root, &mut out);
+    out
+  }
+}
+
+impl<K:Hash+Eq+Clone,V:Clone,S:BuildHasher+Default> PMap<K,V,S> {
+  /// Inserts the entry, copying the path to it first, if this path is
+  /// shared with a clone; returns the value that `k` had, if any.
+  pub fn insert(&mut self, k:K, v:V) -> Option<V> {
+    let hash = Self::hash(&k);
+    let old = Self::insert_at(Rc::make_mut(&mut self.root), 0, hash, k, v);
+    if old.is_none() { self.len += 1 } ;
+    old
+  }
+
+  fn insert_at(node:&mut MapNode<K,V>, shift:u32, hash:u64, k:K, v:V) -> Option<V> {
+    let split = match *node {
+      MapNode::Branch(ref mut children) => {
+        let idx = ((hash >> shift) & MASK) as usize;
+        return match children[idx] {
+          Some(ref mut child) => Self::insert_at(Rc::make_mut(child), shift + BITS, hash, k, v),
+          None => { children[idx] = Some(Rc::new(MapNode::Leaf(vec![(hash, k, v)]))); None },
+        }
+      },
+      MapNode::Leaf(ref mut entries) => {
+        if let Some(entry) = entries.iter_mut().find(|e| e.0 == hash && e.1 == k) {
+          return Some(replace(&mut entry.2, v))
+        } ;
+        if entries.len() < LEAF_MAX || shift >= 64 {
+          entries.push((hash, k, v));
+          return None
+        } ;
+        replace(entries, Vec::new())
+      },
+    };
+    // The leaf is full: replace it with a branch, and reinsert its
+    // entries below this branch.
+    *node = MapNode::Branch(vec![None; WIDTH]);
+    for (h, k2, v2) in split { Self::insert_at(node, shift, h, k2, v2); } ;
+    Self::insert_at(node, shift, hash, k, v)
+  }
+}
+
+impl<K,V,S> Clone for PMap<K,V,S> {
+  fn clone(&self) -> Self {
+    PMap{ len:self.len, root:self.root.clone(), hasher:PhantomData }
+  }
+}
+
+impl<K:Hash+Eq+fmt::Debug,V:fmt::Debug,S:BuildHasher+Default> fmt::Debug for PMap<K,V,S> {
+  fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+    f.debug_map().entries(self.entries()).finish()
+  }
+}
+
+#[test]
+fn pvec_forks_are_independent () {
+  let mut v = PVec::new();
+  for i in 0..5000usize { v.push(i) } ;
+  let mut w = v.clone();
+  for i in 0..5000usize { *w.get_mut(i).unwrap() += 1 } ;
+  w.push(5001);
+  assert_eq!(v.len(), 5000);
+  assert_eq!(w.len(), 5001);
+  assert!(v.iter().cloned().eq(0..5000));
+  assert!(w.iter().cloned().eq(1..5002));
+  assert_eq!(v.get(5000), None);
+}
+
+#[test]
+fn pmap_forks_are_independent () {
+  use std::hash::BuildHasherDefault;
+  use std::collections::hash_map::DefaultHasher;
+  /// Hashes that collide everywhere but in their low byte.
+  #[derive(Default)]
+  struct Coarse(DefaultHasher);
+  impl Hasher for Coarse {
+    fn write(&mut self, bytes:&[u8]) { self.0.write(bytes) }
+    fn finish(&self) -> u64 { self.0.finish() & 0xff }
+  }
+  let mut m : PMap<usize, usize, BuildHasherDefault<Coarse>> = PMap::new();
+  for i in 0..2000 { assert_eq!(m.insert(i, i), None) } ;
+  let mut n = m.clone();
+  for i in 0..2000 { assert_eq!(n.insert(i, i + 1), Some(i)) } ;
+  assert_eq!(n.insert(2000, 2001), None);
+  assert_eq!((m.entries().len(), n.entries().len()), (2000, 2001));
+  for i in 0..2000 {
+    assert_eq!(m.get(&i), Some(&i));
+    assert_eq!(n.get(&i), Some(&(i + 1)));
+  } ;
+  assert_eq!(m.get(&2000), None);
+}
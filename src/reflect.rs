@@ -3,6 +3,8 @@ use macros::ProgPt;
 use std::fmt::Debug;
 use std::rc::Rc;
 use std::collections::HashMap;
+use std::hash::{Hash,BuildHasher};
+use std::convert::TryFrom;
 
 /// This trait consists of the ability for a reference to `Self` to
 /// produce a `T`.  Conceptually, that value of type T is the
@@ -58,6 +60,14 @@ pub enum Val {
   /// Constructor with a sequence of fields (name-value pairs) as parameters.
   Struct(Name,Vec<(Name,Val)>),
 
+  /// A map of key-value entries (e.g., a `HashMap` or `BTreeMap`),
+  /// in the order of the map's iterator.
+  Map(Vec<(Val,Val)>),
+
+  /// A set of values (e.g., a `HashSet` or `BTreeSet`), in the order
+  /// of the set's iterator.
+  Set(Vec<Val>),
+
   /// Named articulation, and its content (an `Art` is either a named value, or a named computation).
   Art(Loc,ArtContent),
 
@@ -70,6 +80,10 @@ pub enum Val {
   /// Temporary; for marking places in code where we should produce a
   /// value, but don't yet have a good way to do so.
   ValTODO,
+
+  /// The `Debug` string of a value that could not be parsed (e.g.,
+  /// because of a custom `Debug` implementation).
+  Unparsed(String),
 }
 
 /// Primitive constants
@@ -79,8 +93,18 @@ pub enum Const {
   Num(isize),
   /// Natural numbers
   Nat(usize),
+  /// Integers that fit in neither a `usize` nor an `isize` (e.g., a
+  /// large `u128`, or a large `u64` on a 32-bit target), in decimal.
+  BigInt(String),
   /// Strings
   String(String),
+  /// Booleans
+  Bool(bool),
+  /// Characters
+  Char(char),
+  /// Floating-point numbers, as printed by `Debug` (e.g., `1.5`,
+  /// `1e-7`, `inf` or `NaN`); floats are neither `Eq` nor `Hash`.
+  Float(String),
 }
 
 /// The content of an articulation: Either a cell holding a value, or
@@ -248,11 +272,330 @@ pub struct DCG {
 }
 
 
-/// Wrapper for `parse_val::parse_val`. Transforms most Rust data
-/// that derives `Debug` into a reflected `Val`.
-pub fn reflect_val <V:Debug> (v:&V) -> Val { 
+/// Transforms Rust data into a reflected `Val`.  Uses the type's
+/// `ReflectVal` implementation, when it has one; otherwise, parses
+/// the value's `Debug` output (see `parse_val`).
+pub fn reflect_val <V:Debug+?Sized> (v:&V) -> Val { 
+    v.reflect_val_or_parse()
+}
+
+/// Reflection of a value into a `Val`, directly.  Optional: the
+/// engine reflects the data that it stores in the DCG with
+/// `reflect_val`, which uses this trait when the data implements it,
+/// and parses the data's `Debug` output otherwise.
+///
+/// Implementing this trait avoids formatting and re-parsing this
+/// `Debug` string, which is slow, and which fails for custom `Debug`
+/// implementations.
+///
+/// Implementations should produce the same `Val` that `parse_val`
+/// produces for the derived `Debug` output of the value (e.g.,
+/// `Constr` for enum variants, and `Struct` for structs with named
+/// fields), and should use `reflect_val` to reflect their
+/// components.
+pub trait ReflectVal {
+  fn reflect_val (&self) -> Val;
+}
+
+/// Dispatches `reflect_val` to `ReflectVal`, or else to `parse_val`.
+trait ReflectValOrParse {
+  fn reflect_val_or_parse (&self) -> Val;
+}
+impl<T:Debug+?Sized> ReflectValOrParse for T {
+  default fn reflect_val_or_parse (&self) -> Val {
     use parse_val::parse_val;
-    parse_val(v)
+    parse_val(self)
+  }
+}
+impl<T:Debug+ReflectVal+?Sized> ReflectValOrParse for T {
+  fn reflect_val_or_parse (&self) -> Val { self.reflect_val() }
+}
+
+impl ReflectVal for usize {
+  fn reflect_val (&self) -> Val { Val::Const(Const::Nat(*self)) }
+}
+impl ReflectVal for isize {
+  // Like `parse_val`, only negative integers are `Num`s.
+  fn reflect_val (&self) -> Val {
+    if *self < 0 { Val::Const(Const::Num(*self)) }
+    else { Val::Const(Const::Nat(*self as usize)) }
+  }
+}
+
+// Integers that fit in neither a `usize` nor an `isize` on this
+// target are `BigInt`s, as in `parse_val`.
+macro_rules! reflect_val_nat {
+  ( $( $t:ty ),* ) => { $(
+    impl ReflectVal for $t {
+      fn reflect_val (&self) -> Val {
+        match usize::try_from(*self) {
+          Ok(n)  => Val::Const(Const::Nat(n)),
+          Err(_) => Val::Const(Const::BigInt(self.to_string())),
+        }
+      }
+    }
+  )* }
+}
+reflect_val_nat!(u8, u16, u32, u64, u128);
+
+macro_rules! reflect_val_num {
+  ( $( $t:ty ),* ) => { $(
+    impl ReflectVal for $t {
+      fn reflect_val (&self) -> Val {
+        let c = if *self < 0 { isize::try_from(*self).ok().map(Const::Num) }
+                else { usize::try_from(*self).ok().map(Const::Nat) };
+        Val::Const(c.unwrap_or_else(|| Const::BigInt(self.to_string())))
+      }
+    }
+  )* }
+}
+reflect_val_num!(i8, i16, i32, i64, i128);
+
+impl ReflectVal for f32 {
+  fn reflect_val (&self) -> Val { Val::Const(Const::Float(format!("{:?}", self))) }
+}
+impl ReflectVal for f64 {
+  fn reflect_val (&self) -> Val { Val::Const(Const::Float(format!("{:?}", self))) }
+}
+impl ReflectVal for bool {
+  fn reflect_val (&self) -> Val { Val::Const(Const::Bool(*self)) }
+}
+impl ReflectVal for char {
+  fn reflect_val (&self) -> Val { Val::Const(Const::Char(*self)) }
+}
+impl ReflectVal for String {
+  fn reflect_val (&self) -> Val { Val::Const(Const::String(self.clone())) }
+}
+impl<'a> ReflectVal for &'a str {
+  fn reflect_val (&self) -> Val { Val::Const(Const::String(self.to_string())) }
+}
+impl ReflectVal for () {
+  fn reflect_val (&self) -> Val { Val::Tuple(vec![]) }
+}
+impl<A:Debug,B:Debug> ReflectVal for (A,B) {
+  fn reflect_val (&self) -> Val {
+    Val::Tuple(vec![reflect_val(&self.0), reflect_val(&self.1)])
+  }
+}
+impl<A:Debug,B:Debug,C:Debug> ReflectVal for (A,B,C) {
+  fn reflect_val (&self) -> Val {
+    Val::Tuple(vec![reflect_val(&self.0), reflect_val(&self.1), reflect_val(&self.2)])
+  }
+}
+impl<A:Debug,B:Debug,C:Debug,D:Debug> ReflectVal for (A,B,C,D) {
+  fn reflect_val (&self) -> Val {
+    Val::Tuple(vec![reflect_val(&self.0), reflect_val(&self.1), reflect_val(&self.2), reflect_val(&self.3)])
+  }
+}
+impl<A:Debug,B:Debug,C:Debug,D:Debug,E:Debug> ReflectVal for (A,B,C,D,E) {
+  fn reflect_val (&self) -> Val {
+    Val::Tuple(vec![reflect_val(&self.0), reflect_val(&self.1), reflect_val(&self.2), reflect_val(&self.3),
+                    reflect_val(&self.4)])
+  }
+}
+impl<A:Debug,B:Debug,C:Debug,D:Debug,E:Debug,F:Debug> ReflectVal for (A,B,C,D,E,F) {
+  fn reflect_val (&self) -> Val {
+    Val::Tuple(vec![reflect_val(&self.0), reflect_val(&self.1), reflect_val(&self.2), reflect_val(&self.3),
+                    reflect_val(&self.4), reflect_val(&self.5)])
+  }
+}
+impl<T:Debug> ReflectVal for Vec<T> {
+  fn reflect_val (&self) -> Val {
+    Val::Vec(self.iter().map(reflect_val).collect())
+  }
+}
+impl<T:Debug> ReflectVal for Option<T> {
+  fn reflect_val (&self) -> Val {
+    use engine::name_of_str;
+    match *self {
+      None => Val::Constr(name_of_str("None"), vec![]),
+      Some(ref x) => Val::Constr(name_of_str("Some"), vec![reflect_val(x)]),
+    }
+  }
+}
+impl<T:Debug> ReflectVal for Box<T> {
+  fn reflect_val (&self) -> Val { reflect_val(&**self) }
+}
+impl<T:Debug> ReflectVal for Rc<T> {
+  fn reflect_val (&self) -> Val { reflect_val(&**self) }
+}
+impl<K:Debug+Eq+Hash,V:Debug,S:BuildHasher> ReflectVal for HashMap<K,V,S> {
+  fn reflect_val (&self) -> Val {
+    Val::Map(self.iter().map(|(k,v)| (reflect_val(k), reflect_val(v))).collect())
+  }
+}
+impl<K:Debug+Ord,V:Debug> ReflectVal for ::std::collections::BTreeMap<K,V> {
+  fn reflect_val (&self) -> Val {
+    Val::Map(self.iter().map(|(k,v)| (reflect_val(k), reflect_val(v))).collect())
+  }
+}
+impl<T:Debug+Eq+Hash,S:BuildHasher> ReflectVal for ::std::collections::HashSet<T,S> {
+  fn reflect_val (&self) -> Val { Val::Set(self.iter().map(reflect_val).collect()) }
+}
+impl<T:Debug+Ord> ReflectVal for ::std::collections::BTreeSet<T> {
+  fn reflect_val (&self) -> Val { Val::Set(self.iter().map(reflect_val).collect()) }
+}
+
+/// Queries over a reflected `DCG`, for debugging tools that ask
+/// questions such as "which thunks depend on this cell?", or "what
+/// is dirty?".  Edges that target locations outside the table (if
+/// any) are ignored.
+pub mod query {
+  use std::collections::{HashMap,HashSet};
+  use macros::ProgPt;
+  use super::{DCG,Loc,Node,Path,Effect,succs_of_node,preds_of_node};
+
+  /// The three kinds of reflected nodes.
+  #[derive(Debug,Clone,Copy,Eq,PartialEq,Hash)]
+  pub enum NodeKind { Comp, Ref, Pure }
+
+  /// The kind of a node.
+  pub fn node_kind (nd:&Node) -> NodeKind {
+    match *nd {
+      Node::Comp(_) => NodeKind::Comp,
+      Node::Ref(_)  => NodeKind::Ref,
+      Node::Pure(_) => NodeKind::Pure,
+    }
+  }
+
+  /// True iff the node has a dirty outgoing edge.
+  pub fn node_is_dirty (nd:&Node) -> bool {
+    match succs_of_node(nd) {
+      None => false,
+      Some(succs) => succs.iter().any(|succ| succ.dirty),
+    }
+  }
+
+  /// True iff the path of `loc` begins with the names of `prefix`.
+  pub fn loc_has_path_prefix (loc:&Loc, prefix:&Path) -> bool {
+    loc.path.len() >= prefix.len() && &loc.path[0..prefix.len()] == &prefix[..]
+  }
+
+  /// The locations of the nodes that satisfy the predicate.
+  pub fn select<'a, P:Fn(&Loc, &Node) -> bool> (dcg:&'a DCG, pred:P) -> Vec<&'a Loc> {
+    dcg.table.iter().filter(|&(loc, nd)| pred(loc, nd)).map(|(loc, _)| loc).collect()
+  }
+
+  /// The locations whose path begins with the given prefix.
+  pub fn select_path_prefix<'a> (dcg:&'a DCG, prefix:&Path) -> Vec<&'a Loc> {
+    select(dcg, |loc, _| loc_has_path_prefix(loc, prefix))
+  }
+
+  /// The locations of the thunks whose code resides at the given program point.
+  pub fn select_prog_pt<'a> (dcg:&'a DCG, prog_pt:&ProgPt) -> Vec<&'a Loc> {
+    select(dcg, |_, nd| match *nd {
+      Node::Comp(ref nd) => &nd.prog_pt == prog_pt,
+      _ => false,
+    })
+  }
+
+  /// The locations of the nodes of the given kind.
+  pub fn select_kind<'a> (dcg:&'a DCG, kind:NodeKind) -> Vec<&'a Loc> {
+    select(dcg, |_, nd| node_kind(nd) == kind)
+  }
+
+  /// The locations of the nodes that are dirty (when `dirty` is
+  /// true), or clean (when `dirty` is false).
+  pub fn select_dirty<'a> (dcg:&'a DCG, dirty:bool) -> Vec<&'a Loc> {
+    select(dcg, |_, nd| node_is_dirty(nd) == dirty)
+  }
+
+  /// The locations of the nodes without predecessors.  For instance,
+  /// the thunks forced (and the cells allocated) only by the editor.
+  pub fn roots<'a> (dcg:&'a DCG) -> Vec<&'a Loc> {
+    select(dcg, |_, nd| match preds_of_node(nd) {
+      None => true,
+      Some(preds) => preds.len() == 0,
+    })
+  }
+
+  /// The locations of the immediate successors (when `fwd` is true)
+  /// or predecessors (otherwise) of `loc`, via edges of the given
+  /// effect, or via any edge when `effect` is `None`.
+  fn neighbors<'a> (dcg:&'a DCG, loc:&Loc, effect:Option<&Effect>, fwd:bool) -> Vec<&'a Loc> {
+    let nd = match dcg.table.get(loc) { None => return vec![], Some(nd) => nd };
+    let ok = |eff:&Effect| match effect { None => true, Some(e) => e == eff };
+    if fwd {
+      match succs_of_node(nd) {
+        None => vec![],
+        Some(succs) => succs.iter().filter(|s| ok(&s.effect))
+          .filter_map(|s| dcg.table.get(&s.loc).map(|_| key_of(dcg, &s.loc))).collect(),
+      }
+    } else {
+      match preds_of_node(nd) {
+        None => vec![],
+        Some(preds) => preds.iter().filter(|p| ok(&p.effect))
+          .filter_map(|p| dcg.table.get(&p.loc).map(|_| key_of(dcg, &p.loc))).collect(),
+      }
+    }
+  }
+
+  /// The table's own copy of the given (present) location.
+  fn key_of<'a> (dcg:&'a DCG, loc:&Loc) -> &'a Loc {
+    match dcg.table.get_key_value(loc) {
+      Some((k, _)) => k,
+      None => unreachable!(),
+    }
+  }
+
+  fn closure<'a> (dcg:&'a DCG, loc:&Loc, effect:Option<&Effect>, fwd:bool) -> HashSet<&'a Loc> {
+    let mut visited = HashSet::new();
+    let mut todo = neighbors(dcg, loc, effect, fwd);
+    while let Some(l) = todo.pop() {
+      if visited.insert(l) {
+        todo.extend(neighbors(dcg, l, effect, fwd))
+      }
+    }
+    visited
+  }
+
+  /// The transitive successors of `loc`, via edges of the given
+  /// effect (or via any edge, when `effect` is `None`).  For
+  /// instance, the `Force` successors of a thunk are all the nodes
+  /// that it depends upon.
+  pub fn succs_closure<'a> (dcg:&'a DCG, loc:&Loc, effect:Option<&Effect>) -> HashSet<&'a Loc> {
+    closure(dcg, loc, effect, true)
+  }
+
+  /// The transitive predecessors of `loc`, via edges of the given
+  /// effect (or via any edge, when `effect` is `None`).  For
+  /// instance, the `Force` predecessors of a cell are all the thunks
+  /// that depend upon it.
+  pub fn preds_closure<'a> (dcg:&'a DCG, loc:&Loc, effect:Option<&Effect>) -> HashSet<&'a Loc> {
+    closure(dcg, loc, effect, false)
+  }
+
+  /// The number of nodes on the longest path of successor edges in
+  /// the DCG (zero for an empty DCG).
+  pub fn depth (dcg:&DCG) -> usize {
+    // Depth of each node, computed bottom-up with an explicit stack;
+    // a node on the stack (in progress) does not count as a successor
+    // again, so that an ill-formed, cyclic graph still terminates.
+    let mut depths : HashMap<&Loc, usize> = HashMap::new();
+    let mut in_progress : HashSet<&Loc> = HashSet::new();
+    let mut max = 0;
+    for root in dcg.table.keys() {
+      let mut stack = vec![(root, false)];
+      while let Some((loc, expanded)) = stack.pop() {
+        if depths.contains_key(loc) { continue };
+        let succs = neighbors(dcg, loc, None, true);
+        if expanded {
+          let d = 1 + succs.iter().filter_map(|s| depths.get(s)).max().map_or(0, |d| *d);
+          in_progress.remove(loc);
+          depths.insert(loc, d);
+          if d > max { max = d }
+        } else if in_progress.insert(loc) {
+          stack.push((loc, true));
+          for s in succs {
+            if !depths.contains_key(s) && !in_progress.contains(s) {
+              stack.push((s, false))
+            }
+          }
+        }
+      }
+    }
+    max
+  }
 }
 
 /// Gives effects and traces for cleaning and dirtying, the engine's
@@ -261,6 +604,7 @@ pub fn reflect_val <V:Debug> (v:&V) -> Val {
 /// itself, not changes that the engine makes to it.
 pub mod trace {
   use std::fmt;
+  use std::io;
 
   /// Distinguish fresh allocations from those that reuse an existing location.
   #[derive(Clone,Debug)]
@@ -387,7 +731,213 @@ pub mod trace {
     pub extent:Box<Vec<Trace>>,
   }
 
-    
+  /// Observes the effects of the DCG as the engine performs them,
+  /// rather than after the fact.  Each effect is reported by a call
+  /// to `on_begin`, and its extent ends with a matching call to
+  /// `on_end`; effects that begin in between are nested within this
+  /// extent.  Effects without an extent (e.g., `CleanEdge`) are
+  /// reported as an `on_begin` immediately followed by an `on_end`.
+  ///
+  /// Listeners are registered with the engine using
+  /// `engine::reflect::dcg_listener_add`.  A listener that is added
+  /// while the engine is within the extent of an effect will observe
+  /// an `on_end` without a matching `on_begin`; listeners should
+  /// ignore these.
+  pub trait TraceListener {
+    /// The beginning of an effect on an edge of the DCG.
+    fn on_begin(&mut self, effect:&Effect, edge:&Edge);
+    /// The end of the extent of the most-recently begun effect.
+    fn on_end(&mut self);
+  }
+
+  /// Builds a forest of `Trace`s from the effects that it observes.
+  /// This is the listener that `dcg_reflect_begin` and
+  /// `dcg_reflect_end` use to record traces.
+  #[derive(Clone,Debug)]
+  pub struct TraceForest {
+    stack:Vec<Box<Vec<Trace>>>,
+  }
+
+  impl TraceForest {
+    pub fn new() -> TraceForest {
+      TraceForest{stack:vec![Box::new(vec![])]}
+    }
+
+    /// Consume the builder, and return the forest of traces that it
+    /// built.  Effects that have begun without ending (e.g., the
+    /// force of a thunk, when a region ends within the thunk's body)
+    /// are closed, with the extents observed so far.
+    pub fn into_traces(mut self) -> Vec<Trace> {
+      while self.stack.len() > 1 { self.on_end() } ;
+      match self.stack.pop() {
+        None => unreachable!(),
+        Some(traces) => *traces
+      }
+    }
+  }
+
+  impl TraceListener for TraceForest {
+    fn on_begin(&mut self, effect:&Effect, edge:&Edge) {
+      match self.stack.last_mut() {
+        None => unreachable!(),
+        Some(ts) => ts.push(Trace{
+          extent: Box::new(vec![]),
+          effect: effect.clone(),
+          edge:   edge.clone(),
+        })
+      };
+      self.stack.push(Box::new(vec![]))
+    }
+
+    fn on_end(&mut self) {
+      // The traces at the top of the stack are popped; they become
+      // the extent of the trace at the end (top) of the second
+      // top-most sequence of traces.
+      if self.stack.len() == 1 {
+        // This effect began before we began listening; ignore it.
+        return
+      };
+      let extent = self.stack.pop();
+      match (extent, self.stack.last_mut()) {
+        (None, _) => unreachable!(),
+        (_, None) => unreachable!(),
+        (Some(extent), Some(trs)) =>
+          match trs.last_mut() {
+            None => unreachable!(),
+            Some(parent) => { assert_eq!(parent.extent.len(), 0);
+                              parent.extent = extent }
+          }
+      }
+    }
+  }
+
+  /// Forwards to another listener only those effects accepted by a
+  /// predicate.  When an effect is rejected, so are the effects
+  /// nested within its extent.
+  pub struct TraceFilter<L:TraceListener, F:Fn(&Effect, &Edge) -> bool> {
+    listener:L,
+    pred:F,
+    /// For each open extent, whether its effect was forwarded.
+    open:Vec<bool>,
+  }
+
+  impl<L:TraceListener, F:Fn(&Effect, &Edge) -> bool> TraceFilter<L,F> {
+    pub fn new(listener:L, pred:F) -> TraceFilter<L,F> {
+      TraceFilter{listener:listener, pred:pred, open:vec![]}
+    }
+    /// Consume the filter, and return the listener that it wraps.
+    pub fn into_listener(self) -> L { self.listener }
+  }
+
+  impl<L:TraceListener, F:Fn(&Effect, &Edge) -> bool> TraceListener for TraceFilter<L,F> {
+    fn on_begin(&mut self, effect:&Effect, edge:&Edge) {
+      let parent_ok = match self.open.last() { None => true, Some(ok) => *ok };
+      let ok = parent_ok && (self.pred)(effect, edge);
+      if ok { self.listener.on_begin(effect, edge) };
+      self.open.push(ok)
+    }
+    fn on_end(&mut self) {
+      match self.open.pop() {
+        None => (), // This effect began before we began listening; ignore it.
+        Some(true) => self.listener.on_end(),
+        Some(false) => (),
+      }
+    }
+  }
+
+  /// Counts the effects that it observes, by kind, without storing
+  /// them.
+  #[derive(Clone,Debug,Default,PartialEq,Eq)]
+  pub struct TraceCounter {
+    pub alloc:      usize,
+    pub force:      usize,
+    pub dirty:      usize,
+    pub clean_rec:  usize,
+    pub clean_edge: usize,
+    pub clean_eval: usize,
+    pub remove:     usize,
+    /// The deepest nesting of effects observed so far.
+    pub max_depth:  usize,
+    depth:usize,
+  }
+
+  impl TraceCounter {
+    pub fn new() -> TraceCounter { TraceCounter::default() }
+    /// The total number of effects observed so far.
+    pub fn total(&self) -> usize {
+      self.alloc + self.force + self.dirty + self.clean_rec +
+        self.clean_edge + self.clean_eval + self.remove
+    }
+  }
+
+  impl TraceListener for TraceCounter {
+    fn on_begin(&mut self, effect:&Effect, _edge:&Edge) {
+      match *effect {
+        Effect::Alloc(_, _) => self.alloc += 1,
+        Effect::Force(_)    => self.force += 1,
+        Effect::Dirty       => self.dirty += 1,
+        Effect::CleanRec    => self.clean_rec += 1,
+        Effect::CleanEdge   => self.clean_edge += 1,
+        Effect::CleanEval   => self.clean_eval += 1,
+        Effect::Remove      => self.remove += 1,
+      };
+      self.depth += 1;
+      if self.depth > self.max_depth { self.max_depth = self.depth }
+    }
+    fn on_end(&mut self) {
+      if self.depth > 0 { self.depth -= 1 }
+    }
+  }
+
+  /// Writes each effect that it observes as a line of text, indented
+  /// by the depth of its nesting (e.g., to a log file).
+  ///
+  /// The first write that fails is recorded (see `error`), and the
+  /// listener writes nothing after it.
+  pub struct TraceWriter<W:io::Write> {
+    writer:W,
+    depth:usize,
+    error:Option<io::Error>,
+  }
+
+  impl<W:io::Write> TraceWriter<W> {
+    pub fn new(writer:W) -> TraceWriter<W> {
+      TraceWriter{writer:writer, depth:0, error:None}
+    }
+    /// The error of the first write that failed, if any.
+    pub fn error(&self) -> Option<&io::Error> { self.error.as_ref() }
+    /// Consume the listener, and return the writer that it wraps, or
+    /// else the error of the first write that failed.
+    pub fn into_writer(self) -> io::Result<W> {
+      match self.error {
+        None => Ok(self.writer),
+        Some(err) => Err(err),
+      }
+    }
+  }
+
+  impl<W:io::Write> TraceListener for TraceWriter<W> {
+    fn on_begin(&mut self, effect:&Effect, edge:&Edge) {
+      use engine::reflect::string_of_loc;
+      self.depth += 1;
+      if self.error.is_some() { return };
+      let src = match edge.loc {
+        None => String::from("editor"),
+        Some(ref loc) => string_of_loc(loc),
+      };
+      match writeln!(self.writer, "{:width$}{:?}: {} --{:?}--> {}", "", effect,
+                     src, edge.succ.effect, string_of_loc(&edge.succ.loc),
+                     width = 2 * (self.depth - 1)) {
+        Ok(()) => (),
+        Err(err) => self.error = Some(err),
+      }
+    }
+    fn on_end(&mut self) {
+      if self.depth > 0 { self.depth -= 1 }
+    }
+  }
+
+
     #[derive(Clone,Copy)]
     pub enum Role { Editor, Archivist }
     
@@ -144,6 +144,617 @@ mod engine_api {
     }
 
     #[test] 
+    fn trace_listener_sees_forest () {
+        // Test that a registered listener observes the same effects,
+        // with the same nesting, as the forest of dcg_reflect_end.
+        use std::rc::Rc;
+        use std::cell::RefCell;
+        use adapton::macros::*;
+        use adapton::engine::*;
+        use adapton::engine::reflect::trace::{Trace,TraceCounter};
+
+        fn size(trs:&Vec<Trace>) -> (usize, usize) {
+            let mut count = 0;
+            let mut depth = 0;
+            for tr in trs.iter() {
+                let (c, d) = size(&tr.extent);
+                count += 1 + c;
+                if d + 1 > depth { depth = d + 1 }
+            }
+            (count, depth)
+        }
+
+        manage::init_dcg();
+        let counter = Rc::new(RefCell::new(TraceCounter::new()));
+        let id = reflect::dcg_listener_add(counter.clone());
+        reflect::dcg_reflect_begin();
+        let c = cell!(1 as usize);
+        let c2 = c.clone();
+        let t = thunk![ get!(c) + 1 ];
+        assert_eq!(force(&t), 2);
+        set(&c2, 2);
+        assert_eq!(force(&t), 3);
+        let traces = reflect::dcg_reflect_end();
+        assert!(reflect::dcg_listener_remove(id).is_some());
+        assert!(reflect::dcg_listener_remove(id).is_none());
+        let counter = counter.borrow();
+        assert_eq!((counter.total(), counter.max_depth), size(&traces));
+    }
+
+    #[test]
+    fn reflect_end_within_listener () {
+        // Test that a region may end while the engine is calling its
+        // listeners, which then still hold the region's forest.
+        use std::rc::Rc;
+        use std::cell::RefCell;
+        use adapton::macros::*;
+        use adapton::engine::*;
+        use adapton::engine::reflect::trace::{Trace,TraceListener,Effect,Edge};
+        struct Ender { traces:Option<Vec<Trace>> }
+        impl TraceListener for Ender {
+            fn on_begin(&mut self, _eff:&Effect, _edge:&Edge) {
+                if self.traces.is_none() { self.traces = Some(reflect::dcg_reflect_end()) }
+            }
+            fn on_end(&mut self) { }
+        }
+        manage::init_dcg();
+        let ender = Rc::new(RefCell::new(Ender{ traces:None }));
+        let id = reflect::dcg_listener_add(ender.clone());
+        reflect::dcg_reflect_begin();
+        let _ = cell!(1 as usize);
+        assert!(reflect::dcg_listener_remove(id).is_some());
+        assert_eq!(ender.borrow().traces.as_ref().map(|trs| trs.len()), Some(0));
+    }
+
+    #[test] 
+    fn validate_dcg_is_well_formed () {
+        use std::rc::Rc;
+        use adapton::macros::*;
+        use adapton::engine::*;
+        manage::init_naive();
+        assert_eq!(manage::validate_dcg(), vec![]);
+        manage::init_dcg();
+        let a = cell!(1);
+        let b = cell!((2, 3));
+        let a1 = a.clone();
+        let t = thunk![{
+            // Validate mid-evaluation, with this thunk on the stack.
+            assert_eq!(manage::validate_dcg(), vec![]);
+            get!(a1) + force_map(&b, |_,x| x.0)
+        }];
+        let u = thunk![ get!(t) * 10 ];
+        assert_eq!(force(&u), 30);
+        assert_eq!(manage::validate_dcg(), vec![]);
+        set(&a, 2);
+        assert_eq!(manage::validate_dcg(), vec![]);
+        assert_eq!(force(&u), 40);
+        assert_eq!(manage::validate_dcg(), vec![]);
+    }
+
+    #[test] 
+    fn force_map_thunk_prunes_reevaluation () {
+        // Test whether cleaning a projection of a thunk re-evaluates
+        // the observer only when the projected value changes.
+        use std::rc::Rc;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use adapton::macros::*;
+        use adapton::engine::*;
+        static EVALS : AtomicUsize = AtomicUsize::new(0);
+        manage::init_dcg();
+        let c  = cell!((1 as usize, 2 as usize));
+        let c1 = c.clone();
+        let p  = thunk![ get!(c1) ];
+        let t  = thunk![{
+            EVALS.fetch_add(1, Ordering::SeqCst);
+            force_map(&p, |_,x| x.0) + 100
+        }];
+        assert_eq!(force(&t), 101);
+        assert_eq!(EVALS.load(Ordering::SeqCst), 1);
+        set(&c, (1, 3));
+        assert_eq!(force(&t), 101);
+        assert_eq!(EVALS.load(Ordering::SeqCst), 1);
+        set(&c, (4, 3));
+        assert_eq!(force(&t), 104);
+        assert_eq!(EVALS.load(Ordering::SeqCst), 2);
+    }
+
+    #[test] 
+    fn force_map_thunk_two_projections () {
+        use std::rc::Rc;
+        use adapton::macros::*;
+        use adapton::engine::*;
+        manage::init_dcg();
+        let c  = cell!((1 as usize, 2 as usize));
+        let c1 = c.clone();
+        let p  = thunk![ get!(c1) ];
+        let t  = thunk![ force_map(&p, |_,x| x.0) * 10 + force_map(&p, |_,x| x.1) ];
+        assert_eq!(force(&t), 12);
+        set(&c, (1, 3));
+        assert_eq!(force(&t), 13);
+        set(&c, (5, 3));
+        assert_eq!(force(&t), 53);
+    }
+
+    #[test] 
+    fn update_cell_in_place () {
+        use std::rc::Rc;
+        use adapton::macros::*;
+        use adapton::engine::*;
+        manage::init_dcg();
+        let c  = cell!(vec![1, 2, 3]);
+        let c1 = c.clone();
+        let t  = thunk![ get!(c1).iter().sum::<usize>() ];
+        assert_eq!(force(&t), 6);
+        update(&c, |v| { v.push(4); true });
+        assert_eq!(force(&t), 10);
+        // The closure reports no change, so `t` is not dirtied, and
+        // keeps its (now stale) cached result.
+        update(&c, |v| { v[0] = 0; false });
+        assert_eq!(force(&t), 10);
+        assert_eq!(force(&c), vec![0, 2, 3, 4]);
+    }
+
+    #[test]
+    fn update_observed_cell_does_not_copy () {
+        use std::cell::Cell;
+        use std::rc::Rc;
+        use adapton::macros::*;
+        use adapton::engine::*;
+        thread_local!(static CLONES : Cell<usize> = Cell::new(0));
+        #[derive(Debug,PartialEq,Eq,Hash)]
+        struct Counted(Vec<usize>);
+        impl Clone for Counted {
+            fn clone(&self) -> Self {
+                CLONES.with(|c| c.set(c.get() + 1));
+                Counted(self.0.clone())
+            }
+        }
+        fn clones () -> usize { CLONES.with(|c| c.replace(0)) }
+        manage::init_dcg();
+        let c  = cell(name_of_str("c"), Counted(vec![1, 2, 3]));
+        let c1 = c.clone();
+        let t  = thunk![ force_with(&c1, |v:&Counted| v.0.iter().sum::<usize>()) ];
+        let c2 = c.clone();
+        let u  = thunk![ force(&c2).0.len() ];
+        assert_eq!((force(&t), force(&u)), (6, 3));
+        let _ = clones();
+        update(&c, |v| { v.0.push(4); true });
+        assert_eq!(clones(), 0);
+        assert_eq!((force(&t), force(&u)), (10, 4));
+        let _ = clones();
+        update(&c, |v| { v.0[0] = 0; false });
+        update(&c, |v| { v.0.push(5); true });
+        assert_eq!(clones(), 0);
+        assert_eq!((force(&t), force(&u)), (14, 5));
+        assert_eq!(manage::validate_dcg(), vec![]);
+    }
+
+    #[test]
+    #[should_panic(expected = "under the Naive engine")]
+    fn update_panics_under_naive () {
+        use adapton::engine::*;
+        manage::init_dcg();
+        let c = cell(name_of_str("c"), 1);
+        manage::init_naive();
+        update(&c, |v| { *v += 1; true })
+    }
+
+    #[test] 
+    fn peek_records_no_dependency () {
+        use std::rc::Rc;
+        use adapton::macros::*;
+        use adapton::engine::*;
+        manage::init_dcg();
+        let c  = cell!(1);
+        let c1 = c.clone();
+        let t  = thunk![ get!(c1) + 1 ];
+        assert_eq!(peek(&c), Some(1));
+        assert_eq!(peek(&t), None);
+        assert_eq!(force(&t), 2);
+        assert_eq!(peek(&t), Some(2));
+        let c2 = c.clone();
+        let u  = thunk![ peek(&c2).unwrap() * 10 ];
+        assert_eq!(force(&u), 10);
+        set(&c, 5);
+        // `u` does not observe `c`, so it keeps its cached result.
+        assert_eq!(force(&u), 10);
+        // `t` is dirty, but `peek` reports its stale result.
+        assert_eq!(peek(&t), Some(2));
+        assert_eq!(force(&t), 6);
+    }
+
+    #[test] 
+    fn force_with_borrows_value () {
+        use std::rc::Rc;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use adapton::macros::*;
+        use adapton::engine::*;
+        static CLONES : AtomicUsize = AtomicUsize::new(0);
+        #[derive(Hash,PartialEq,Eq,Debug)]
+        struct Big(Vec<usize>);
+        impl Clone for Big {
+            fn clone(&self) -> Big { CLONES.fetch_add(1, Ordering::SeqCst); Big(self.0.clone()) }
+        }
+        manage::init_dcg();
+        let c = cell!(Big(vec![1, 2, 3]));
+        let clones = CLONES.load(Ordering::SeqCst);
+        assert_eq!(force_with(&c, |b| b.0.len()), 3);
+        assert_eq!(CLONES.load(Ordering::SeqCst), clones);
+        let c1 = c.clone();
+        let t = thunk![ force_with(&c1, |b| b.0.iter().sum::<usize>()) ];
+        assert_eq!(force(&t), 6);
+        // Within a thunk, the recorded dependency shares the value.
+        assert_eq!(CLONES.load(Ordering::SeqCst), clones);
+        set(&c, Big(vec![4]));
+        assert_eq!(force(&t), 4);
+    }
+
+    #[test] 
+    fn fx_hasher_is_deterministic () {
+        use std::hash::{Hash, Hasher};
+        use adapton::engine::FxHasher;
+        fn fx<T:Hash>(x:T) -> u64 {
+            let mut h = FxHasher::default();
+            x.hash(&mut h);
+            h.finish()
+        }
+        assert_eq!(fx(1u64), 0x517cc1b727220a95);
+        assert_eq!(fx(1usize), fx(1u64));
+        assert_eq!(fx("name"), fx(String::from("name")));
+        assert!(fx("name") != fx("nam"));
+        let fx_bytes = |b:&[u8]| { let mut h = FxHasher::default(); h.write(b); h.finish() };
+        assert!(fx_bytes(b"a") != fx_bytes(b"a\0"));
+        assert!(fx_bytes(b"") != fx_bytes(b"\0"));
+    }
+
+    #[test] 
+    fn use_hash_fn_sip () {
+        use std::rc::Rc;
+        use adapton::macros::*;
+        use adapton::engine::*;
+        let old = manage::use_hash_fn(HashFn::Sip);
+        assert_eq!(old, HashFn::Fx);
+        manage::init_dcg();
+        let c  = cell!(1);
+        let c1 = c.clone();
+        let t  = thunk![ get!(c1) + 1 ];
+        assert_eq!(force(&t), 2);
+        set(&c, 2);
+        assert_eq!(force(&t), 3);
+        // Names and arts now use SipHash, so switching back is rejected,
+        // but keeping the same function is not.
+        assert_eq!(manage::use_hash_fn(HashFn::Sip), HashFn::Sip);
+    }
+
+    #[test]
+    #[should_panic(expected = "after hashing names or arts")]
+    fn use_hash_fn_rejects_switch_after_hashing () {
+        use adapton::engine::*;
+        let _ = name_of_str("a");
+        manage::use_hash_fn(HashFn::Sip);
+    }
+
+    #[test] 
+    fn interned_names () {
+        use adapton::engine::*;
+        let n = name_pair(name_of_str("a"), name_of_usize(1));
+        assert_eq!(n, name_pair(name_of_string(String::from("a")), name_of_usize(1)));
+        assert!(n != name_pair(name_of_usize(1), name_of_str("a")));
+        let (l, r) = name_fork(n.clone());
+        assert!(l != r);
+        assert_eq!(l, name_fork(n.clone()).0);
+        // Interning leaves the (parsable) `Debug` form unchanged.
+        assert_eq!(format!("{:?}", n), "Pair(String(\"a\"), Usize(1))");
+        assert_eq!(format!("{:?}", l), "ForkL(Pair(String(\"a\"), Usize(1)))");
+    }
+
+    #[test] 
+    fn name_fork_n_and_name_gen () {
+        use adapton::engine::*;
+        use adapton::engine::reflect::string_of_name;
+        let n = name_of_str("n");
+        assert_eq!(name_fork_n(n.clone(), 0), vec![]);
+        assert_eq!(name_fork_n(n.clone(), 1), vec![n.clone()]);
+        let (n1, n2, n3, n4) = name_fork4(n.clone());
+        assert_eq!(name_fork_n(n.clone(), 4), vec![n1, n2, n3, n4]);
+        let gen : Vec<Name> = NameGen::new(n.clone()).take(3).collect();
+        assert_eq!(&gen[..], &name_fork_n(n.clone(), 4)[..3]);
+        assert_eq!(string_of_name(&gen[1]), "n-r-l");
+        assert_eq!(string_of_name(&name_of_u64(7)), "7u64");
+        assert_eq!(string_of_name(&name_of_bytes(&[0, 255])), "0x00ff");
+        assert_eq!(string_of_name(&name_of_hash64(255)), "#00000000000000ff");
+        assert_eq!(name_of_hash(&("k", 1)), name_of_hash(&("k", 1)));
+        assert!(name_of_hash(&("k", 1)) != name_of_hash(&("k", 2)));
+    }
+
+    use std::hash::{Hash,Hasher};
+
+    /// An argument whose hashes always collide.
+    #[derive(Clone,PartialEq,Eq,Debug)]
+    struct Collide(usize);
+    impl Hash for Collide {
+        fn hash<H:Hasher>(&self, _h:&mut H) { }
+    }
+
+    fn thunk_of_collide (c:Collide) -> ::adapton::engine::Art<usize> {
+        use std::rc::Rc;
+        use adapton::macros::*;
+        use adapton::engine::*;
+        thunk(ArtIdChoice::Structural,
+              prog_pt!("thunk_of_collide"),
+              Rc::new(Box::new(|c:Collide, ()| c.0 * 10)),
+              c, ())
+    }
+
+    #[test] 
+    fn structural_thunks_resolve_collisions () {
+        use adapton::engine::*;
+        manage::init_dcg();
+        let t1 = thunk_of_collide(Collide(1));
+        let t2 = thunk_of_collide(Collide(2));
+        assert!(t1 != t2);
+        assert_eq!(force(&t1), 10);
+        assert_eq!(force(&t2), 20);
+        // Re-allocating finds the existing thunks, past the collision.
+        assert_eq!(thunk_of_collide(Collide(2)), t2);
+        assert_eq!(thunk_of_collide(Collide(1)), t1);
+    }
+
+    fn init_dcg_verify () {
+        use adapton::engine::*;
+        manage::init_dcg();
+        let engine = manage::init_naive();
+        if let Engine::DCG(ref dcg) = engine {
+            dcg.borrow_mut().flags.verify_cache_hits = true
+        };
+        manage::use_engine(engine);
+    }
+
+    #[test] 
+    fn verify_cache_hits_of_pure_thunks () {
+        use std::rc::Rc;
+        use adapton::macros::*;
+        use adapton::engine::*;
+        init_dcg_verify();
+        let a = cell!(1);
+        let a1 = a.clone();
+        let t = thunk![ get!(a1) + 1 ];
+        let t1 = t.clone();
+        let u = thunk![ get!(t1) * 10 ];
+        assert_eq!(force(&u), 20);
+        assert_eq!(force(&u), 20);
+        set(&a, 2);
+        assert_eq!(force(&u), 30);
+        assert_eq!(force(&t), 3);
+    }
+
+    #[test] 
+    #[should_panic(expected = "verify: cache hit")]
+    fn verify_cache_hits_detects_impure_thunk () {
+        use std::rc::Rc;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use adapton::macros::*;
+        use adapton::engine::*;
+        static COUNT : AtomicUsize = AtomicUsize::new(0);
+        init_dcg_verify();
+        let t = thunk![ COUNT.fetch_add(1, Ordering::SeqCst) ];
+        force(&t);
+        force(&t);
+    }
+
+    #[test] 
+    fn verify_cache_hits_in_isolation () {
+        use std::rc::Rc;
+        use std::panic::{catch_unwind,AssertUnwindSafe};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use adapton::macros::*;
+        use adapton::engine::*;
+        static RUNS : AtomicUsize = AtomicUsize::new(0);
+        init_dcg_verify();
+        // The rerun's allocation (of `runs`, with a new value) leaves
+        // the DCG untouched.
+        let t = thunk![ cell(name_of_str("runs"), RUNS.fetch_add(1, Ordering::SeqCst)) ];
+        let runs = force(&t);
+        assert_eq!(force(&t), runs);
+        assert_eq!(RUNS.load(Ordering::SeqCst), 2);
+        assert_eq!(force(&runs), 0);
+        assert_eq!(manage::validate_dcg(), vec![]);
+        // A rerun that panics leaves no frame on the stack, and
+        // verification on.
+        let u = thunk![ assert!(RUNS.fetch_add(1, Ordering::SeqCst) < 3) ];
+        force(&u);
+        assert!(catch_unwind(AssertUnwindSafe(|| force(&u))).is_err());
+        set(&runs, 5);
+        assert!(catch_unwind(AssertUnwindSafe(|| force(&u))).is_err());
+        assert_eq!(force(&runs), 5);
+        assert_eq!(manage::validate_dcg(), vec![]);
+    }
+
+    /// A chain of `n` thunks, each forcing the one before it, over a
+    /// cell; built bottom-up, so that its initial evaluation is shallow.
+    fn thunk_chain (c:&::adapton::engine::Art<usize>, n:usize) -> ::adapton::engine::Art<usize> {
+        use std::rc::Rc;
+        use adapton::macros::*;
+        use adapton::engine::*;
+        let mut t = c.clone();
+        for _ in 0..n {
+            let prev = t.clone();
+            t = thunk![ get!(prev) + 1 ];
+            let _ = force(&t);
+        } ;
+        t
+    }
+
+    #[test] 
+    fn deep_chains_dirty_and_clean_iteratively () {
+        use std::thread;
+        use adapton::engine::*;
+        // A small stack, which recursion per edge of the chain would overflow.
+        let child = thread::Builder::new().stack_size(1 << 20).spawn(|| {
+            manage::init_dcg();
+            // Checking well-formedness after each step (see
+            // `ADAPTON_CHECK_DCG`) would take quadratic time here.
+            let engine = manage::init_naive();
+            if let Engine::DCG(ref dcg) = engine {
+                dcg.borrow_mut().flags.check_dcg_is_wf = false
+            };
+            manage::use_engine(engine);
+            let n = 100000;
+            let c = cell(name_of_str("c"), 0);
+            let t = thunk_chain(&c, n);
+            assert_eq!(force(&t), n);
+            set(&c, 1);
+            assert_eq!(force(&t), n + 1);
+            // Changes that do not reach the top of the chain.
+            let d = cell(name_of_str("d"), 0);
+            let u = thunk_chain(&d, n);
+            set(&d, 1);
+            set(&d, 0);
+            assert_eq!(force(&u), n);
+        }).unwrap();
+        child.join().unwrap();
+    }
+
+    #[test]
+    fn wide_nodes_update_edges_in_constant_time () {
+        use std::rc::Rc;
+        use adapton::macros::*;
+        use adapton::engine::*;
+        manage::init_dcg();
+        // As above, checking well-formedness would take quadratic time.
+        let engine = manage::init_naive();
+        if let Engine::DCG(ref dcg) = engine {
+            dcg.borrow_mut().flags.check_dcg_is_wf = false
+        };
+        manage::use_engine(engine);
+        let n = 20000;
+        // One cell with many observers, ...
+        let c = cell(name_of_str("c"), 0);
+        let ts : Vec<Art<usize>> = (0..n).map(|i| {
+            let c = c.clone();
+            thunk![ get!(c) + i ]
+        }).collect();
+        // ... and one thunk with many successors.
+        let ds : Vec<Art<usize>> = (0..n).map(|i| cell(name_pair(name_of_str("d"), name_of_usize(i)), i)).collect();
+        let sum = {
+            let ds = ds.clone();
+            thunk![ ds.iter().map(|d| get!(d)).sum::<usize>() ]
+        };
+        let total = n * (n - 1) / 2;
+        assert_eq!(force(&sum), total);
+        for round in 1..4 {
+            set(&c, round);
+            for (i, t) in ts.iter().enumerate() {
+                assert_eq!(force(t), round + i)
+            } ;
+            set(&ds[round], 0);
+            set(&ds[round], round);
+            assert_eq!(force(&sum), total);
+        }
+    }
+
+    #[test]
+    fn arts_resolve_in_a_later_dcg () {
+        use adapton::engine::*;
+        manage::init_dcg();
+        let c1 = cell(name_of_str("c"), 1);
+        // A fresh DCG, which allocates its nodes in a different order.
+        manage::init_dcg();
+        let _ = cell(name_of_str("b"), 0);
+        let c2 = cell(name_of_str("c"), 2);
+        assert_eq!(force(&c1), 2);
+        set(&c1, 3);
+        assert_eq!(force(&c2), 3);
+    }
+
+    #[test]
+    fn speculate_in_forks () {
+        use std::rc::Rc;
+        use adapton::macros::*;
+        use adapton::engine::*;
+        manage::init_dcg();
+        let c = cell(name_of_str("c"), 1);
+        let t = { let c = c.clone(); thunk![ get!(c) * 10 ] };
+        assert_eq!(force(&t), 10);
+        // Many alternative edits against the same baseline.
+        for i in 2..6 {
+            let (x, fork) = manage::speculate(|| { set(&c, i); force(&t) });
+            assert_eq!(x, i * 10);
+            assert_eq!(peek(&c), Some(1));
+            assert_eq!(peek(&t), Some(10));
+            let original = manage::use_engine(fork);
+            assert_eq!(manage::validate_dcg(), vec![]);
+            let _ = manage::use_engine(original);
+        } ;
+        assert_eq!(manage::validate_dcg(), vec![]);
+        // Adopt a fork.
+        let (_, fork) = manage::speculate(|| set(&c, 7));
+        let _ = manage::use_engine(fork);
+        assert_eq!(peek(&t), Some(10));
+        assert_eq!(force(&t), 70);
+    }
+
+    #[test]
+    fn forks_share_nodes_until_written () {
+        use adapton::engine::*;
+        fn dcg_eq (e1:&Engine, e2:&Engine) -> bool {
+            match (e1, e2) {
+                (&Engine::DCG(ref d1), &Engine::DCG(ref d2)) => *d1.borrow() == *d2.borrow(),
+                _ => unreachable!(),
+            }
+        }
+        manage::init_dcg();
+        let c = cell(name_of_str("c"), 1);
+        let original = manage::use_engine(manage::fork_engine());
+        // (A fork of the fork in use is equal to it.)
+        assert!(dcg_eq(&original, &manage::fork_engine()));
+        // (Peeking does not write.)
+        assert_eq!(peek(&c), Some(1));
+        assert!(dcg_eq(&original, &manage::fork_engine()));
+        set(&c, 2);
+        assert!(!dcg_eq(&original, &manage::fork_engine()));
+        let _ = manage::use_engine(original);
+        assert_eq!(force(&c), 1);
+    }
+
+    #[test]
+    fn speculate_restores_engine_on_panic () {
+        use std::panic::{catch_unwind,AssertUnwindSafe};
+        use adapton::engine::*;
+        manage::init_dcg();
+        let c = cell(name_of_str("c"), 1);
+        let r = catch_unwind(AssertUnwindSafe(|| manage::speculate(|| { set(&c, 2); panic!("speculation fails") })));
+        assert!(r.is_err());
+        assert!(!manage::engine_is_naive());
+        assert_eq!(force(&c), 1);
+        assert_eq!(manage::validate_dcg(), vec![]);
+    }
+
+    #[test]
+    fn intern_shares_pure_nodes () {
+        use std::rc::Rc;
+        use adapton::macros::*;
+        use adapton::engine::*;
+        manage::init_naive();
+        assert_eq!(force(&intern(5)), 5);
+        manage::init_dcg();
+        let a = intern(vec![1, 2, 3]);
+        let b = ns(name_of_str("elsewhere"), || intern(vec![1, 2, 3]));
+        let c = intern(vec![4]);
+        assert_eq!(a, b);
+        assert!(a != c);
+        let a1 = a.clone();
+        let t = thunk![ force(&a1).len() + force_map(&b, |_, v| v[0]) ];
+        assert_eq!(force(&t), 4);
+        let dump = reflect::string_of_dcg(&reflect::dcg_reflect_now().unwrap());
+        // One node per distinct value, and no edges.
+        assert_eq!(dump.lines().filter(|l| l.starts_with("pure ")).count(), 2);
+        assert_eq!(dump.lines().filter(|l| l.starts_with("  ")).count(), 0);
+        assert_eq!(manage::validate_dcg(), vec![]);
+        assert_eq!(force(&c), vec![4]);
+    }
+
+    #[test]
     fn force_map_thunk () {
         use std::rc::Rc;
         use adapton::macros::*;
@@ -155,3 +766,367 @@ mod engine_api {
         assert_eq!(a as u64, c);
     }
 }
+
+mod engine_reflect {
+    //! This module tests the recording of DCG traces by `reflect`
+
+    /// Builds a cell `c`, and a thunk `t` that allocates a cell `d`
+    /// and observes both cells; allocates `b` before or after `c`.
+    /// Returns `c`.
+    fn golden_dcg (b_first:bool) -> ::adapton::engine::Art<usize> {
+        use std::rc::Rc;
+        use adapton::macros::ProgPt;
+        use adapton::engine::*;
+        if b_first { let _ = cell(name_of_str("b"), 0); } ;
+        let c = cell(name_of_str("c"), 1);
+        let c2 = c.clone();
+        if !b_first { let _ = cell(name_of_str("b"), 0); } ;
+        let t = thunk(ArtIdChoice::Nominal(name_of_str("t")),
+                      ProgPt{symbol:"t"},
+                      Rc::new(Box::new(move |(),()| {
+                          let d = cell(name_of_str("d"), 2);
+                          force(&c) + force(&d)
+                      })), (), ());
+        assert_eq!(force(&t), 3);
+        c2
+    }
+
+    #[test]
+    fn dcg_dump_is_canonical () {
+        use adapton::engine::*;
+        manage::init_dcg();
+        set(&golden_dcg(false), 5);
+        let dump = reflect::string_of_dcg(&reflect::dcg_reflect_now().unwrap());
+        assert_eq!(dump, "\
+path __
+ref ___b = Const(Nat(0))
+ref ___c = Const(Nat(5))
+  pred force ___t
+ref ___d = Const(Nat(2))
+  pred alloc ___t
+  pred force ___t
+comp ___t \"t\" = Const(Nat(3))
+  dirty force ___c = Const(Nat(1))
+  alloc ___d = Const(Nat(2))
+  force ___d = Const(Nat(2))
+");
+        // Equivalent DCGs, allocated in different orders.
+        manage::init_dcg();
+        set(&golden_dcg(true), 5);
+        let dump2 = reflect::string_of_dcg(&reflect::dcg_reflect_now().unwrap());
+        assert_eq!(dump, dump2);
+    }
+
+    /// A set, hashed independently of its iteration order.
+    #[derive(Clone,PartialEq,Eq,Debug)]
+    struct Bag(::std::collections::HashSet<usize>);
+    impl ::std::hash::Hash for Bag {
+        fn hash<H: ::std::hash::Hasher>(&self, h:&mut H) { ::std::hash::Hash::hash(&self.0.iter().sum::<usize>(), h) }
+    }
+    impl ::adapton::engine::reflect::ReflectVal for Bag {
+        fn reflect_val (&self) -> ::adapton::engine::reflect::Val {
+            ::adapton::engine::reflect::reflect_val(&self.0)
+        }
+    }
+
+    #[test]
+    fn dcg_dump_sorts_sets () {
+        use adapton::engine::*;
+        use std::collections::HashSet;
+        let dump_of = |cap:usize, rev:bool| {
+            manage::init_dcg();
+            let mut s = HashSet::with_capacity(cap);
+            let mut ns : Vec<usize> = (0..20).collect();
+            if rev { ns.reverse() } ;
+            for n in ns { s.insert(n * 1000) ; }
+            let _ = cell(name_of_str("s"), Bag(s));
+            reflect::string_of_dcg(&reflect::dcg_reflect_now().unwrap())
+        };
+        let dump = dump_of(0, false);
+        assert!(dump.contains("Set([Const(Nat(0)), Const(Nat(1000)), Const(Nat(10000)),"));
+        assert_eq!(dump, dump_of(1000, true));
+    }
+
+    #[test]
+    fn reflect_nested_regions () {
+        // Test that an inner region records only its own effects,
+        // while the outer region records all of them.
+        use adapton::macros::*;
+        use adapton::engine::*;
+        manage::init_dcg();
+        reflect::dcg_reflect_begin();
+        let a = cell!(1 as usize);
+        reflect::dcg_reflect_begin();
+        let b = cell!(2 as usize);
+        let c = cell!(3 as usize);
+        let inner = reflect::dcg_reflect_end();
+        let d = cell!(4 as usize);
+        let outer = reflect::dcg_reflect_end();
+        drop((a, b, c, d));
+        assert_eq!(inner.len(), 2);
+        assert_eq!(outer.len(), 4);
+        assert_eq!(format!("{:?}", inner),
+                   format!("{:?}", &outer[1..3]));
+    }
+
+    #[test]
+    fn reflect_nested_region_within_thunk () {
+        // Test that a region that begins and ends within a thunk
+        // records the thunk's effects, which the outer region records
+        // within the extent of forcing the thunk.
+        use std::rc::Rc;
+        use std::cell::RefCell;
+        use adapton::macros::*;
+        use adapton::engine::*;
+        manage::init_dcg();
+        let inner : Rc<RefCell<Vec<Vec<reflect::trace::Trace>>>> = Rc::new(RefCell::new(vec![]));
+        let inner2 = inner.clone();
+        reflect::dcg_reflect_begin();
+        let c = cell!(1 as usize);
+        let t = thunk![{
+            reflect::dcg_reflect_begin();
+            let x = get!(c) + 1;
+            inner2.borrow_mut().push(reflect::dcg_reflect_end());
+            x
+        }];
+        assert_eq!(force(&t), 2);
+        let outer = reflect::dcg_reflect_end();
+        let inner = inner.borrow();
+        assert_eq!(inner.len(), 1);
+        assert_eq!(inner[0].len(), 1);
+        assert_eq!(outer.len(), 3);
+        assert_eq!(format!("{:?}", inner[0]),
+                   format!("{:?}", outer[2].extent));
+    }
+
+    #[test]
+    fn reflect_region_ends_within_thunk () {
+        // Test that a region that begins outside a thunk, and ends
+        // within it, closes the (unfinished) force of the thunk.
+        use std::rc::Rc;
+        use std::cell::RefCell;
+        use adapton::macros::*;
+        use adapton::engine::*;
+        use adapton::engine::reflect::trace::{Effect,ForceCase};
+        manage::init_dcg();
+        let traces : Rc<RefCell<Vec<reflect::trace::Trace>>> = Rc::new(RefCell::new(vec![]));
+        let traces2 = traces.clone();
+        let c = cell!(1 as usize);
+        let t = thunk![{
+            let x = get!(c) + 1;
+            *traces2.borrow_mut() = reflect::dcg_reflect_end();
+            x
+        }];
+        reflect::dcg_reflect_begin();
+        assert_eq!(force(&t), 2);
+        let traces = traces.borrow();
+        assert_eq!(traces.len(), 1);
+        match traces[0].effect {
+            Effect::Force(ForceCase::CompCacheMiss) => (), ref eff => panic!("{:?}", eff)
+        } ;
+        assert_eq!(traces[0].extent.len(), 1);
+    }
+
+    #[test]
+    fn reflect_edge_values () {
+        // Test that reflected edges carry the values that flowed
+        // along them, both in traces and in the reflected DCG.
+        use std::rc::Rc;
+        use adapton::macros::*;
+        use adapton::engine::*;
+        use adapton::engine::reflect::{Val,Const,Node};
+        use adapton::engine::reflect::trace::{Effect,ForceCase};
+        manage::init_dcg();
+        reflect::dcg_reflect_begin();
+        let c = cell!((1234 as usize, 5678 as usize));
+        let t = thunk![ force_map(&c, |_,x| x.1) + 1 ];
+        assert_eq!(force(&t), 5679);
+        let traces = reflect::dcg_reflect_end();
+        assert_eq!(traces[0].edge.succ.value,
+                   Val::Tuple(vec![Val::Const(Const::Nat(1234)),
+                                   Val::Const(Const::Nat(5678))]));
+        let force_tr = traces.last().unwrap();
+        let get_tr = force_tr.extent.iter().find(|tr| match tr.effect {
+            Effect::Force(ForceCase::RefGet) => true, _ => false }).unwrap();
+        assert_eq!(get_tr.edge.succ.value, Val::Const(Const::Nat(5678)));
+
+        let dcg = reflect::dcg_reflect_now().unwrap();
+        let t_loc = force_tr.edge.succ.loc.clone();
+        match dcg.table.get(&t_loc) {
+            Some(&Node::Comp(ref nd)) => {
+                assert_eq!(nd.succs.len(), 1);
+                assert_eq!(nd.succs[0].value, Val::Const(Const::Nat(5678)));
+                assert_eq!(nd.value, Some(Val::Const(Const::Nat(5679))));
+            },
+            _ => panic!("expected a reflected thunk"),
+        }
+    }
+
+    #[test]
+    fn query_reflected_dcg () {
+        // Test queries over a chain of thunks that depend on a cell.
+        use std::rc::Rc;
+        use adapton::macros::*;
+        use adapton::engine::*;
+        use adapton::engine::reflect::Effect;
+        use adapton::engine::reflect::query::*;
+        manage::init_dcg();
+        let c = cell!(1 as usize);
+        let c2 = c.clone();
+        let t1 = ns(name_of_str("inner"), || thunk![ get!(c) + 1 ]);
+        let t2 = thunk![ get!(t1) * 2 ];
+        let root = thunk![ get!(t2) + 3 ];
+        assert_eq!(force(&root), 7);
+
+        let dcg = reflect::dcg_reflect_now().unwrap();
+        assert_eq!(dcg.table.len(), 4);
+        assert_eq!(select_kind(&dcg, NodeKind::Ref).len(), 1);
+        assert_eq!(select_kind(&dcg, NodeKind::Comp).len(), 3);
+        let c_loc = select_kind(&dcg, NodeKind::Ref)[0].clone();
+        let inner = select_path_prefix(&dcg, &vec![name_of_str("inner")]);
+        assert_eq!(inner.len(), 1);
+        assert_eq!(select_path_prefix(&dcg, &vec![]).len(), 4);
+        assert_eq!(select_dirty(&dcg, true).len(), 0);
+        assert_eq!(depth(&dcg), 4);
+
+        // Each thunk depends on the cell; the root depends on everything else.
+        let deps = preds_closure(&dcg, &c_loc, Some(&Effect::Force));
+        assert_eq!(deps.len(), 3);
+        let roots = roots(&dcg);
+        assert_eq!(roots.len(), 1);
+        assert!(deps.contains(roots[0]));
+        assert_eq!(succs_closure(&dcg, roots[0], None).len(), 3);
+        assert_eq!(succs_closure(&dcg, &c_loc, None).len(), 0);
+        let inner_pt = match dcg.table.get(inner[0]) {
+            Some(&reflect::Node::Comp(ref nd)) => nd.prog_pt.clone(),
+            _ => panic!("expected a thunk"),
+        };
+        // (The thunk! macro uses one program point for all anonymous thunks)
+        assert_eq!(select_prog_pt(&dcg, &inner_pt).len(), 3);
+
+        // After changing the cell, everything that depends on it is dirty.
+        set(&c2, 2);
+        let dcg = reflect::dcg_reflect_now().unwrap();
+        assert_eq!(select_dirty(&dcg, true).len(), 3);
+        assert_eq!(select_dirty(&dcg, false), vec![&c_loc]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn reflect_end_without_begin () {
+        use adapton::engine::*;
+        manage::init_dcg();
+        reflect::dcg_reflect_begin();
+        let _ = reflect::dcg_reflect_end();
+        let _ = reflect::dcg_reflect_end();
+    }
+}
+
+mod engine_differential {
+    //! This module tests the differential harness, which compares
+    //! the Naive and DCG engines over a script of input edits
+
+    use std::rc::Rc;
+    use adapton::catalog::collections::*;
+    use adapton::catalog::testing::*;
+    use adapton::engine::*;
+
+    /// Prepends the elements, in order, to an initially-empty list.
+    struct Prepend(Vec<usize>);
+
+    impl EditScript for Prepend {
+        type Input = List<usize>;
+        fn init (&self) -> List<usize> { List::nil() }
+        fn edit_count (&self) -> usize { self.0.len() }
+        fn edit (&self, step:usize, l:List<usize>) -> List<usize> {
+            let i = self.0[step];
+            let l = List::art(cell(name_of_usize(i), l));
+            let l = List::name(name_of_usize(i), l);
+            List::cons(i, l)
+        }
+    }
+
+    fn sum_of_list (l:List<usize>) -> usize {
+        let t = ns(name_of_str("tree_of_list"),
+                   || tree_of_list::<_,_,Tree<_>,_>(Dir2::Left, l));
+        monoid_of_tree(t, 0, Rc::new(|x,y| x + y))
+    }
+
+    #[test]
+    fn differential_sum () {
+        differential(sum_of_list, Prepend(vec![1,2,3,4,5,6,7,8,9]))
+    }
+
+    #[test]
+    #[should_panic(expected = "differs from naive")]
+    fn differential_detects_divergence () {
+        // The output depends on the engine in use, so the runs diverge.
+        differential(|l:List<usize>| (sum_of_list(l), manage::engine_is_dcg()),
+                     Prepend(vec![1,2]))
+    }
+
+    #[test]
+    fn differential_restores_engine_on_panic () {
+        use std::panic;
+        manage::init_dcg();
+        let res = panic::catch_unwind(|| {
+            differential(|l:List<usize>| -> usize { if manage::engine_is_naive() { panic!("naive run") } ; sum_of_list(l) },
+                         Prepend(vec![1,2]))
+        });
+        assert!(res.is_err());
+        assert!(manage::engine_is_dcg());
+    }
+}
+
+mod catalog_cellmap {
+    //! This module tests that `CellMap` tracks dependencies per key
+
+    use std::rc::Rc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use adapton::macros::*;
+    use adapton::engine::*;
+    use adapton::catalog::cellmap::CellMap;
+
+    static EVALS_A : AtomicUsize = AtomicUsize::new(0);
+    static EVALS_C : AtomicUsize = AtomicUsize::new(0);
+
+    #[test]
+    fn cellmap_dirties_per_key () {
+        manage::init_dcg();
+        let mut m : CellMap<&'static str, usize> = CellMap::new(name_of_str("m"));
+        m.insert("a", 1);
+        let (m1, m2, m3) = (m.clone(), m.clone(), m.clone());
+        let ta = thunk![{ EVALS_A.fetch_add(1, Ordering::SeqCst); m1.get(&"a") }];
+        let tc = thunk![{ EVALS_C.fetch_add(1, Ordering::SeqCst); m2.get(&"c") }];
+        let tn = thunk![ m3.entries() ];
+        assert_eq!(force(&ta), Some(1));
+        assert_eq!(force(&tc), None);
+        assert_eq!(force(&tn), vec![("a", 1)]);
+
+        // A new key dirties readers of the key set, but not of other keys.
+        assert_eq!(m.insert("b", 2), None);
+        assert_eq!(force(&ta), Some(1));
+        assert_eq!(force(&tc), None);
+        assert_eq!(force(&tn), vec![("a", 1), ("b", 2)]);
+        assert_eq!(EVALS_A.load(Ordering::SeqCst), 1);
+        assert_eq!(EVALS_C.load(Ordering::SeqCst), 1);
+
+        // Changing a key dirties its readers.
+        assert_eq!(m.insert("a", 3), Some(1));
+        assert_eq!(force(&ta), Some(3));
+        assert_eq!(force(&tc), None);
+        assert_eq!(EVALS_A.load(Ordering::SeqCst), 2);
+        assert_eq!(EVALS_C.load(Ordering::SeqCst), 1);
+
+        // Inserting an absent key dirties its readers.
+        m.insert("c", 4);
+        assert_eq!(force(&tc), Some(4));
+        assert_eq!(EVALS_C.load(Ordering::SeqCst), 2);
+
+        assert_eq!(m.remove(&"a"), Some(3));
+        assert_eq!(force(&ta), None);
+        assert_eq!(force(&tn), vec![("b", 2), ("c", 4)]);
+        assert_eq!(m.len(), 2);
+        assert!(!m.contains_key(&"a"));
+    }
+}
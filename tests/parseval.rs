@@ -0,0 +1,289 @@
+extern crate adapton;
+
+mod parse_val_std {
+    //! This module tests that `parse_val` parses the `Debug` output of
+    //! standard Rust types into reflected values.
+
+    use std::collections::{HashMap,HashSet,BTreeMap};
+    use std::rc::Rc;
+    use adapton::parse_val::parse_val;
+    use adapton::engine::reflect::{Val,Const};
+    use adapton::engine::name_of_str;
+
+    fn nat(n:usize) -> Val { Val::Const(Const::Nat(n)) }
+    fn string(s:&str) -> Val { Val::Const(Const::String(String::from(s))) }
+
+    #[test]
+    fn parse_options () {
+        let some : Option<usize> = Some(3);
+        let none : Option<usize> = None;
+        assert_eq!(parse_val(&some), Val::Constr(name_of_str("Some"), vec![nat(3)]));
+        assert_eq!(parse_val(&none), Val::Constr(name_of_str("None"), vec![]));
+        assert_eq!(parse_val(&vec![None, Some(1)]),
+                   Val::Vec(vec![Val::Constr(name_of_str("None"), vec![]),
+                                 Val::Constr(name_of_str("Some"), vec![nat(1)])]));
+    }
+
+    #[test]
+    fn parse_primitives () {
+        assert_eq!(parse_val(&true), Val::Const(Const::Bool(true)));
+        assert_eq!(parse_val(&'x'), Val::Const(Const::Char('x')));
+        assert_eq!(parse_val(&'\n'), Val::Const(Const::Char('\n')));
+        assert_eq!(parse_val(&'\''), Val::Const(Const::Char('\'')));
+        assert_eq!(parse_val(&-42), Val::Const(Const::Num(-42)));
+        assert_eq!(parse_val(&1.5f32), Val::Const(Const::Float(String::from("1.5"))));
+        assert_eq!(parse_val(&-2.0f64), Val::Const(Const::Float(String::from("-2.0"))));
+        assert_eq!(parse_val(&1e-7f64), Val::Const(Const::Float(String::from("1e-7"))));
+        assert_eq!(parse_val(&::std::f64::NEG_INFINITY), Val::Const(Const::Float(String::from("-inf"))));
+        assert_eq!(parse_val(&::std::f64::NAN), Val::Const(Const::Float(String::from("NaN"))));
+        assert_eq!(parse_val(&(-1, 2.5, false)),
+                   Val::Tuple(vec![Val::Const(Const::Num(-1)),
+                                   Val::Const(Const::Float(String::from("2.5"))),
+                                   Val::Const(Const::Bool(false))]));
+    }
+
+    #[test]
+    fn parse_strings () {
+        assert_eq!(parse_val(&"say \"hi\"\\\t"), string("say \"hi\"\\\t"));
+        assert_eq!(parse_val(&"λ ☃ \u{7}"), string("λ ☃ \u{7}"));
+        assert_eq!(parse_val(&String::from("a, b: {c}")), string("a, b: {c}"));
+    }
+
+    #[test]
+    fn parse_maps_and_sets () {
+        let mut m = HashMap::new();
+        m.insert(1, "one");
+        assert_eq!(parse_val(&m), Val::Map(vec![(nat(1), string("one"))]));
+
+        let mut b = BTreeMap::new();
+        b.insert("x", vec![1, 2]);
+        b.insert("y", vec![]);
+        assert_eq!(parse_val(&b),
+                   Val::Map(vec![(string("x"), Val::Vec(vec![nat(1), nat(2)])),
+                                 (string("y"), Val::Vec(vec![]))]));
+
+        let mut s = HashSet::new();
+        s.insert(7);
+        assert_eq!(parse_val(&s), Val::Set(vec![nat(7)]));
+
+        let e : HashMap<usize,usize> = HashMap::new();
+        assert_eq!(parse_val(&e), Val::Map(vec![]));
+    }
+
+    #[derive(Debug)]
+    struct Unit;
+
+    #[derive(Debug)]
+    #[allow(dead_code)]
+    struct Fields { unit:Unit, boxed:Box<Rc<usize>> }
+
+    #[test]
+    fn parse_structs () {
+        assert_eq!(parse_val(&Unit), Val::Constr(name_of_str("Unit"), vec![]));
+        assert_eq!(parse_val(&Fields{unit:Unit, boxed:Box::new(Rc::new(3))}),
+                   Val::Struct(name_of_str("Fields"),
+                               vec![(name_of_str("unit"), Val::Constr(name_of_str("Unit"), vec![])),
+                                    (name_of_str("boxed"), nat(3))]));
+    }
+
+    /// A user type whose constructors share names with those of names.
+    #[derive(Debug)]
+    #[allow(dead_code)]
+    enum Shape { Pair(usize, usize), ForkL(usize), ForkR(usize) }
+
+    #[test]
+    fn parse_user_constructors () {
+        for (v, cons, args) in vec![(Shape::Pair(1, 2), "Pair", vec![nat(1), nat(2)]),
+                                    (Shape::ForkL(3), "ForkL", vec![nat(3)]),
+                                    (Shape::ForkR(7), "ForkR", vec![nat(7)])] {
+            assert_eq!(parse_val(&v), Val::Constr(name_of_str(cons), args));
+        }
+    }
+
+    #[test]
+    fn parse_big_integers () {
+        let big = 1u128 << 100;
+        assert_eq!(parse_val(&big), Val::Const(Const::BigInt(big.to_string())));
+        assert_eq!(parse_val(&(-(big as i128), 1)),
+                   Val::Tuple(vec![Val::Const(Const::BigInt(format!("-{}", big))), nat(1)]));
+    }
+
+    struct Custom;
+    impl ::std::fmt::Debug for Custom {
+        fn fmt(&self, f:&mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+            write!(f, "<custom @ 0x1234>")
+        }
+    }
+
+    #[test]
+    fn parse_fallback () {
+        assert_eq!(parse_val(&Custom), Val::Unparsed(String::from("<custom @ 0x1234>")));
+        assert_eq!(parse_val(&vec![Custom]), Val::Unparsed(String::from("[<custom @ 0x1234>]")));
+    }
+}
+
+mod reflect_val {
+    //! This module tests that `ReflectVal` implementations agree with
+    //! `parse_val`, and that reflection prefers them.
+
+    use std::fmt;
+    use adapton::catalog::collections::{List,ListIntro};
+    use adapton::parse_val::parse_val;
+    use adapton::engine::*;
+    use adapton::engine::reflect::{Val,Const,Node,ReflectVal,reflect_val};
+
+    #[test]
+    fn reflect_val_agrees_with_parse_val () {
+        manage::init_dcg();
+        let l : List<(usize,isize)> = List::nil();
+        let l = List::cons((1, -1), l);
+        let l = List::art(cell(name_of_str("a"), l));
+        let l = List::name(name_of_usize(2), l);
+        let l = List::cons((2, 3), l);
+        assert_eq!(l.reflect_val(), parse_val(&l));
+        let v = (Some(vec![1.5, -2.0]), 'c', true, String::from("s"));
+        assert_eq!(v.reflect_val(), parse_val(&v));
+        let n = name_pair(name_of_str("x"), name_of_usize(3));
+        assert_eq!(n.reflect_val(), parse_val(&n));
+        // Outside of locations, `U64` and `Bytes` constructors are not
+        // read as names, but within them, they are.
+        let n = name_pair(name_of_u64(4), name_of_bytes(b"by"));
+        assert_eq!(parse_val(&n),
+                   Val::Constr(name_of_str("Pair"),
+                               vec![Val::Constr(name_of_str("U64"), vec![Val::Const(Const::Nat(4))]),
+                                    Val::Constr(name_of_str("Bytes"), vec![Val::Vec(vec![Val::Const(Const::Nat(98)),
+                                                                                         Val::Const(Const::Nat(121))])])]));
+        let a = cell(n, 5usize);
+        assert_eq!(a.reflect_val(), parse_val(&a));
+        let n = name_fork(name_of_hash(&"h")).1;
+        assert_eq!(n.reflect_val(), parse_val(&n));
+        // The id of a structural art is printed as its hash.
+        let t = thunk(ArtIdChoice::Structural, ::adapton::macros::ProgPt{ symbol:"t" },
+                      ::std::rc::Rc::new(Box::new(|x:usize, ()| x + 1)), 1, ());
+        assert_eq!(t.reflect_val(), parse_val(&t));
+    }
+
+    /// A value whose `Debug` output does not parse.
+    #[derive(Clone,PartialEq,Eq,Hash)]
+    struct Opaque(usize);
+    impl fmt::Debug for Opaque {
+        fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result { write!(f, "<opaque {}>", self.0) }
+    }
+    impl ReflectVal for Opaque {
+        fn reflect_val(&self) -> Val { Val::Const(Const::Nat(self.0)) }
+    }
+
+    /// A value that does not implement `ReflectVal`.
+    #[derive(Clone,Debug,PartialEq,Eq,Hash)]
+    #[allow(dead_code)]
+    struct Plain { x:usize, y:Option<isize> }
+
+    #[test]
+    fn reflect_val_falls_back_to_parse_val () {
+        let p = Plain{ x:1, y:Some(-2) };
+        assert_eq!(reflect_val(&p), parse_val(&p));
+        assert_eq!(reflect_val(&vec![p]), Val::Vec(vec![parse_val(&Plain{ x:1, y:Some(-2) })]));
+        manage::init_dcg();
+        let a = cell(name_of_str("plain"), Plain{ x:2, y:None });
+        assert_eq!(reflect_val(&a), parse_val(&a));
+    }
+
+    #[test]
+    fn reflect_val_wide_integers () {
+        assert_eq!(reflect_val(&::std::u64::MAX), parse_val(&::std::u64::MAX));
+        assert_eq!(reflect_val(&::std::i64::MIN), parse_val(&::std::i64::MIN));
+        assert_eq!(reflect_val(&::std::u128::MAX), Val::Const(Const::BigInt(::std::u128::MAX.to_string())));
+        assert_eq!(reflect_val(&::std::u128::MAX), parse_val(&::std::u128::MAX));
+        assert_eq!(reflect_val(&::std::i128::MIN), parse_val(&::std::i128::MIN));
+    }
+
+    #[test]
+    fn reflect_val_prefers_trait () {
+        assert_eq!(parse_val(&Opaque(3)), Val::Unparsed(String::from("<opaque 3>")));
+        assert_eq!(reflect_val(&Opaque(3)), Val::Const(Const::Nat(3)));
+        assert_eq!(reflect_val(&vec![Opaque(3)]), Val::Vec(vec![Val::Const(Const::Nat(3))]));
+
+        manage::init_dcg();
+        let _ = cell(name_of_str("opaque"), Opaque(4));
+        let dcg = reflect::dcg_reflect_now().unwrap();
+        let vals : Vec<Val> = dcg.table.values().map(|nd| match *nd {
+            Node::Ref(ref nd) => nd.value.clone(),
+            _ => panic!("expected a reflected cell"),
+        }).collect();
+        assert_eq!(vals, vec![Val::Const(Const::Nat(4))]);
+    }
+}
+
+mod parse_concise {
+    //! This module tests that `parse_name`, `parse_path` and
+    //! `parse_loc` invert `write_name`, `write_path` and `write_loc`.
+
+    use std::rc::Rc;
+    use adapton::macros::ProgPt;
+    use adapton::engine::*;
+    use adapton::engine::reflect::*;
+
+    fn names () -> Vec<Name> {
+        let s = name_of_str("s");
+        vec![name_unit(), name_of_hash64(0xdead), name_of_hash(&"h"),
+             s.clone(), name_of_str("snake_case"), name_of_str("l"), name_of_str("r"),
+             name_of_str("a-b"), name_of_str("x__y"), name_of_str("trail_"), name_of_str("3d"),
+             name_of_string(String::from("say \"hi\"\n")), name_of_str(""),
+             name_of_usize(0), name_of_isize(7), name_of_isize(-7), name_of_isize(::std::isize::MIN),
+             name_of_u64(::std::u64::MAX), name_of_bytes(&[]), name_of_bytes(&[1, 0xab]),
+             name_pair(s.clone(), name_of_usize(1)),
+             name_pair(name_pair(s.clone(), s.clone()), s.clone()),
+             name_pair(s.clone(), name_pair(s.clone(), s.clone())),
+             name_pair(s.clone(), name_of_isize(-1)),
+             name_pair(s.clone(), name_fork(s.clone()).1),
+             name_fork(name_pair(s.clone(), name_of_str("l"))).0,
+             name_fork4(name_pair(name_unit(), name_of_u64(2))).2]
+    }
+
+    #[test]
+    fn names_round_trip () {
+        for n in names() {
+            let s = string_of_name(&n);
+            assert_eq!(parse_name(&s), Ok(n.clone()), "{}", s);
+        }
+    }
+
+    #[test]
+    fn paths_and_locs_round_trip () {
+        let ns = names();
+        for p in vec![vec![], ns.clone()] {
+            assert_eq!(parse_path(&string_of_path(&p)), Ok(p.clone()));
+            for n in ns.iter() {
+                let l = Loc{ path:p.clone(), name:n.clone() };
+                assert_eq!(parse_loc(&string_of_loc(&l)), Ok(l.clone()), "{}", string_of_loc(&l));
+            }
+        }
+    }
+
+    #[test]
+    fn dcg_locs_round_trip () {
+        manage::init_dcg();
+        let c = ns(name_of_str("ns"), || cell(name_of_str("c"), 1));
+        let t = thunk(ArtIdChoice::Structural, ProgPt{ symbol:"t" },
+                      Rc::new(Box::new(|c:Art<usize>, ()| force(&c) + 1)), c, ());
+        assert_eq!(force(&t), 2);
+        let dcg = dcg_reflect_now().unwrap();
+        assert!(dcg.table.len() >= 2);
+        for loc in dcg.table.keys() {
+            assert_eq!(parse_loc(&string_of_loc(loc)).as_ref(), Ok(loc));
+        }
+    }
+
+    #[test]
+    fn ambiguous_input_fails () {
+        for s in vec!["l", "r", "s-l_", "a__b", "", "s-", "(s", "3d", "0x1", "#"] {
+            assert!(parse_name(s).is_err(), "{}", s);
+        }
+        for s in vec!["a", "__a", "__a___", "____a", "__a____b", "__a__b"] {
+            assert!(parse_loc(s).is_err(), "{}", s);
+        }
+        assert_eq!(parse_loc("__a___b").map(|l| (l.path, l.name)),
+                   Ok((vec![name_of_str("a")], name_of_str("b"))));
+        assert!(parse_name("l").unwrap_err().contains("ambiguous"));
+    }
+}